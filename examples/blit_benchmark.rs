@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+use rpi_led_panel::{
+    HardwareMapping, PiChip, RGBMatrix, RGBMatrixConfig, SimulatedRegisterBackend, ThreadScheduling,
+};
+
+const FRAMES: u32 = 200;
+
+/// Blit full frames onto the canvas of a wall of 4 chained by 3 parallel 64x64 panels and report how many
+/// frames per second the blit alone allows. The matrix runs on the simulated backend, so this works on any
+/// machine. Run it with `--release`.
+fn main() {
+    let config = RGBMatrixConfig {
+        hardware_mapping: HardwareMapping::regular(),
+        rows: 64,
+        cols: 64,
+        chain_length: 4,
+        parallel: 3,
+        pi_chip: Some(PiChip::BCM2711),
+        // Leave the system alone, nothing is shown on real panels.
+        pin_update_thread: false,
+        disable_rt_throttling: false,
+        performance_governor: false,
+        update_thread_scheduling: ThreadScheduling::Unchanged,
+        ..Default::default()
+    };
+    let (_matrix, mut canvas) =
+        RGBMatrix::new_with_backend(config, 0, |_| Ok(SimulatedRegisterBackend::new()))
+            .expect("Matrix initialization failed");
+    let (width, height) = (canvas.width(), canvas.height());
+
+    let frames: Vec<Vec<u8>> = (0..2)
+        .map(|frame| {
+            (0..width * height * 3)
+                .map(|i| ((i * 7 + frame * 101) % 256) as u8)
+                .collect()
+        })
+        .collect();
+
+    let mut total = Duration::ZERO;
+    for frame in 0..FRAMES {
+        let data = &frames[frame as usize % frames.len()];
+        let start = Instant::now();
+        canvas.blit_rgb(data, width * 3, 0, 0, width, height);
+        total += start.elapsed();
+    }
+    let per_frame = total / FRAMES;
+    println!(
+        "{width}x{height} pixels: {per_frame:?} per blit, {:.0} blits per second",
+        1.0 / per_frame.as_secs_f64()
+    );
+}
@@ -29,7 +29,7 @@ fn main() {
     for step in 0.. {
         canvas.fill(0, 0, 0);
         image.draw(canvas.as_mut()).unwrap();
-        canvas = matrix.update_on_vsync(canvas);
+        canvas = matrix.update_on_vsync(canvas).unwrap();
 
         if step % 120 == 0 {
             print!("\r{:>100}\rFramerate: {}", "", matrix.get_framerate());
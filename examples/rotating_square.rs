@@ -59,7 +59,7 @@ fn main() {
             }
         }
 
-        canvas = matrix.update_on_vsync(canvas);
+        canvas = matrix.update_on_vsync(canvas).unwrap();
 
         if step % 120 == 0 {
             print!("\r{:>100}\rFramerate: {}", "", matrix.get_framerate());
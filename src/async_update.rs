@@ -0,0 +1,267 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        mpsc::{Receiver, TryRecvError, TrySendError},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use crate::{Canvas, RGBMatrix, UpdateError};
+
+/// The wakers of pending futures and streams, woken by the update thread.
+#[derive(Default)]
+pub(crate) struct WakerSlot {
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl WakerSlot {
+    /// Register a waker. It has to be registered before checking the channel the update thread sends on, so
+    /// no wake-up is lost.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wake all registered wakers.
+    pub(crate) fn wake(&self) {
+        let wakers =
+            std::mem::take(&mut *self.wakers.lock().unwrap_or_else(PoisonError::into_inner));
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// The future returned by [`RGBMatrix::update_on_vsync_async`]. It resolves to a canvas that is no longer
+/// shown, or to the error if the update thread stopped. Dropping it before cancels the update, see
+/// [`RGBMatrix::update_on_vsync_async`].
+#[must_use = "futures do nothing unless polled"]
+pub struct UpdateFuture<'a> {
+    matrix: &'a mut RGBMatrix,
+    /// The canvas until the update thread has taken it.
+    canvas: Option<(Box<Canvas>, usize)>,
+    /// Whether the update thread has taken the canvas and not returned one for it yet.
+    sent: bool,
+}
+
+impl<'a> UpdateFuture<'a> {
+    pub(crate) fn new(matrix: &'a mut RGBMatrix, mut canvas: Box<Canvas>, frames: usize) -> Self {
+        matrix.apply_reconfiguration(&mut canvas);
+        Self {
+            matrix,
+            canvas: Some((canvas, frames)),
+            sent: false,
+        }
+    }
+}
+
+impl Future for UpdateFuture<'_> {
+    type Output = Result<Box<Canvas>, UpdateError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Self {
+            matrix,
+            canvas,
+            sent,
+        } = &mut *self;
+        matrix.update_waker.register(cx.waker());
+
+        if let Some(canvas_and_frames) = canvas.take() {
+            // The canvases of cancelled updates come first.
+            match matrix.receive_owed_canvases(false) {
+                Ok(true) => {}
+                Ok(false) => {
+                    *canvas = Some(canvas_and_frames);
+                    return Poll::Pending;
+                }
+                Err(error) => return Poll::Ready(Err(error)),
+            }
+            match matrix.canvas_to_thread_sender.try_send(canvas_and_frames) {
+                Ok(()) => {
+                    *sent = true;
+                    matrix.frame_rate_monitor.update();
+                }
+                Err(TrySendError::Full(canvas_and_frames)) => {
+                    *canvas = Some(canvas_and_frames);
+                    return Poll::Pending;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    return Poll::Ready(Err(matrix.thread_stopped()))
+                }
+            }
+        }
+
+        match matrix.canvas_from_thread_receiver.try_recv() {
+            Ok(mut canvas) => {
+                *sent = false;
+                matrix.apply_reconfiguration(&mut canvas);
+                Poll::Ready(Ok(canvas))
+            }
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => {
+                *sent = false;
+                Poll::Ready(Err(matrix.thread_stopped()))
+            }
+        }
+    }
+}
+
+impl Drop for UpdateFuture<'_> {
+    fn drop(&mut self) {
+        if let Some((canvas, _)) = self.canvas.take() {
+            self.matrix.spare_canvases.push(*canvas);
+        }
+        if self.sent {
+            self.matrix.owed_canvases += 1;
+        }
+    }
+}
+
+/// A stream of the GPIO inputs as specified with
+/// [`RGBMatrixBuilder::requested_inputs`](crate::RGBMatrixBuilder::requested_inputs), created with
+/// [`RGBMatrix::input_stream`]. A new value is produced whenever the inputs change.
+///
+/// The stream does not depend on a particular runtime. [`InputStream::poll_next`] has the signature of
+/// `Stream::poll_next` of the `futures` crate, so it can be wrapped in a `Stream` if needed.
+pub struct InputStream {
+    receiver: Arc<Mutex<Receiver<u32>>>,
+    waker: Arc<WakerSlot>,
+}
+
+impl InputStream {
+    pub(crate) fn new(receiver: Arc<Mutex<Receiver<u32>>>, waker: Arc<WakerSlot>) -> Self {
+        Self { receiver, waker }
+    }
+
+    /// Poll for the next change of the inputs. Returns `None` once the matrix is dropped and all changes
+    /// have been received.
+    pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u32>> {
+        self.waker.register(cx.waker());
+        let receiver = self.receiver.lock().unwrap_or_else(PoisonError::into_inner);
+        match receiver.try_recv() {
+            Ok(inputs) => Poll::Ready(Some(inputs)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+
+    /// Wait for the next change of the inputs.
+    pub fn next_input(&mut self) -> NextInput<'_> {
+        NextInput { stream: self }
+    }
+}
+
+/// The future returned by [`InputStream::next_input`].
+#[must_use = "futures do nothing unless polled"]
+pub struct NextInput<'a> {
+    stream: &'a mut InputStream,
+}
+
+impl Future for NextInput<'_> {
+    type Output = Option<u32>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::Thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        simulated_backend::test_support::test_config, RGBMatrixBuilder, SimulatedRegisterBackend,
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Drive a future to completion on the current thread.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn test_async_update() {
+        let backend = SimulatedRegisterBackend::new();
+        let probe = backend.clone();
+        let (mut matrix, mut canvas) = RGBMatrixBuilder::new(test_config())
+            .requested_inputs(u32::MAX)
+            .build_with_backend(move |_| Ok(backend))
+            .unwrap();
+        let enabled_input_bits = matrix.enabled_input_bits();
+        assert_ne!(enabled_input_bits, 0);
+        let mut inputs = matrix.input_stream();
+
+        for _ in 0..3 {
+            canvas = block_on(matrix.update_on_vsync_async(canvas)).unwrap();
+        }
+        assert!(matrix.refresh_count() >= 2);
+
+        probe.set_input_levels(enabled_input_bits);
+        assert_eq!(block_on(inputs.next_input()), Some(enabled_input_bits));
+
+        drop(matrix);
+        assert_eq!(block_on(inputs.next_input()), None);
+    }
+
+    #[test]
+    fn test_cancelled_update() {
+        let (mut matrix, canvas) = RGBMatrixBuilder::new(test_config())
+            .build_with_backend(|_| Ok(SimulatedRegisterBackend::new()))
+            .unwrap();
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+
+        // Poll updates once and drop them, until one was dropped after the update thread took its canvas.
+        let mut canvas = canvas;
+        let start = Instant::now();
+        while matrix.owed_canvases == 0 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            let mut future = Box::pin(matrix.update_on_vsync_async(canvas));
+            let poll = future.as_mut().poll(&mut cx);
+            drop(future);
+            canvas = match poll {
+                Poll::Ready(result) => result.unwrap(),
+                Poll::Pending if matrix.owed_canvases == 0 => {
+                    // The canvas was not handed over and is kept.
+                    assert_eq!(matrix.spare_canvases.len(), 1);
+                    matrix.create_offscreen_canvas()
+                }
+                Poll::Pending => matrix.create_offscreen_canvas(),
+            };
+            assert!(matrix.spare_canvases.is_empty());
+        }
+        assert_eq!(matrix.owed_canvases, 1);
+
+        // The next update waits for its own frame instead of taking the canvas of the cancelled one.
+        let _canvas = matrix.update_on_vsync(canvas).unwrap();
+        assert_eq!(matrix.owed_canvases, 0);
+        assert_eq!(matrix.spare_canvases.len(), 1);
+        // No canvas is left over for a later update.
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(matrix.canvas_from_thread_receiver.try_recv().is_err());
+    }
+}
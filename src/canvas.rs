@@ -1,8 +1,19 @@
-use std::{error::Error, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    str::FromStr,
+    sync::Arc,
+};
 
 use crate::{
-    color::ColorLookup, config::K_BIT_PLANES, gpio::Gpio, hardware_mapping::HardwareMapping,
-    row_address_setter::RowAddressSetter, RGBMatrixConfig,
+    color::{ColorLookup, FRACTION_BITS},
+    config::K_BIT_PLANES,
+    dithering::{quantize_ordered, ErrorDiffusion, SpatialDithering},
+    gpio::Gpio,
+    hardware_mapping::HardwareMapping,
+    register_backend::RegisterBackend,
+    row_address_setter::RowAddressSetter,
+    RGBMatrixConfig,
 };
 
 #[derive(Clone, Copy)]
@@ -40,8 +51,21 @@ impl FromStr for LedSequence {
     }
 }
 
+impl Display for LedSequence {
+    /// The name as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 impl LedSequence {
-    fn get_gpio(self, channel: Channel, red_bits: u32, green_bits: u32, blue_bits: u32) -> u32 {
+    pub(crate) fn get_gpio(
+        self,
+        channel: Channel,
+        red_bits: u32,
+        green_bits: u32,
+        blue_bits: u32,
+    ) -> u32 {
         match channel {
             Channel::First => match self {
                 LedSequence::Rgb | LedSequence::Rbg => red_bits,
@@ -139,6 +163,12 @@ impl PixelDesignatorMap {
         self.buffer.get(position)
     }
 
+    /// The designators of `len` consecutive pixels of a row, starting at `(x, y)`.
+    fn row(&self, x: usize, y: usize, len: usize) -> &[PixelDesignator] {
+        let position = (y * self.width) + x;
+        &self.buffer[position..position + len]
+    }
+
     pub(crate) fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut PixelDesignator> {
         let position = (y * self.width) + x;
         self.buffer.get_mut(position)
@@ -157,6 +187,14 @@ impl PixelDesignatorMap {
     }
 }
 
+/// A pixel of a blit, converted from its packed values.
+#[derive(Clone, Copy)]
+enum BlitColor {
+    Rgb([u8; 3]),
+    /// Linear 16 bit values like in [`Canvas::set_pixel_u16`].
+    Linear([u16; 3]),
+}
+
 #[derive(Clone)]
 pub struct Canvas {
     #[allow(unused)]
@@ -164,16 +202,28 @@ pub struct Canvas {
     cols: usize,
     double_rows: usize,
     bitplane_buffer: Vec<u32>,
+    /// The colors as they were set, in visible coordinates.
+    pixels: Vec<[u8; 3]>,
+    /// The linear 16 bit values of the pixels that were set with them, so they are converted again through
+    /// the linear path. Only allocated once a pixel is set with 16 bit values.
+    linear_pixels: Option<Vec<Option<[u16; 3]>>>,
     shared_mapper: PixelDesignatorMap,
     pwm_bits: usize,
     brightness: u8,
-    color_lookup: ColorLookup,
+    /// Shared by all canvases of a matrix until it is reconfigured.
+    color_lookup: Arc<ColorLookup>,
+    spatial_dithering: SpatialDithering,
     interlaced: bool,
+    /// The number of reconfigurations of the matrix that were applied to the canvas.
+    reconfiguration_generation: u64,
 }
 
 impl Canvas {
     pub(crate) fn new(config: &RGBMatrixConfig, shared_mapper: PixelDesignatorMap) -> Self {
-        let color_lookup = ColorLookup::new_cie1931();
+        let color_lookup = Arc::new(ColorLookup::new(
+            &config.color_correction,
+            config.white_balance,
+        ));
         let rows = config.rows * config.parallel;
         let cols = config.cols * config.chain_length;
         let double_rows = config.double_rows();
@@ -182,11 +232,15 @@ impl Canvas {
             cols,
             double_rows,
             bitplane_buffer: vec![0u32; double_rows * cols * K_BIT_PLANES],
+            pixels: vec![[0; 3]; shared_mapper.width * shared_mapper.height],
+            linear_pixels: None,
             shared_mapper,
             pwm_bits: config.pwm_bits,
             brightness: config.led_brightness.clamp(1, 100),
             color_lookup,
+            spatial_dithering: config.spatial_dithering,
             interlaced: config.interlaced,
+            reconfiguration_generation: 0,
         }
     }
 
@@ -218,46 +272,296 @@ impl Canvas {
         if x >= self.width() || y >= self.height() {
             return;
         }
+        self.write_pixel(x, y, [r, g, b]);
+    }
+
+    /// Set a pixel with linear 16 bit values per channel, where `u16::MAX` is fully on. The values are not
+    /// color corrected, but the brightness and the white balance are applied. This uses all bit planes, so
+    /// gradients and dark colors are finer than with [`Canvas::set_pixel`].
+    ///
+    /// [`Canvas::get_pixel`] returns the upper 8 bits of every channel. Changing the brightness, the color
+    /// correction or the white balance converts the pixel again from the full 16 bit values.
+    pub fn set_pixel_u16(&mut self, x: usize, y: usize, r: u16, g: u16, b: u16) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+        self.write_pixel_u16(x, y, [r, g, b]);
+    }
+
+    /// Set a pixel that is known to be on the canvas.
+    #[inline]
+    fn write_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        let luminance = self.store_pixel(x, y, color);
+        self.write_luminance(x, y, luminance);
+    }
+
+    /// Set a pixel with linear 16 bit values that is known to be on the canvas.
+    #[inline]
+    fn write_pixel_u16(&mut self, x: usize, y: usize, rgb: [u16; 3]) {
+        let luminance = self.store_pixel_u16(x, y, rgb);
+        self.write_luminance(x, y, luminance);
+    }
+
+    /// Remember the color of a pixel and look up its luminance.
+    #[inline]
+    fn store_pixel(&mut self, x: usize, y: usize, [r, g, b]: [u8; 3]) -> [u16; 3] {
+        let index = y * self.width() + x;
+        self.pixels[index] = [r, g, b];
+        if let Some(linear_pixels) = &mut self.linear_pixels {
+            linear_pixels[index] = None;
+        }
+        self.color_lookup.lookup_rgb(self.brightness, r, g, b)
+    }
+
+    /// Remember a pixel with linear 16 bit values and scale its luminance.
+    #[inline]
+    fn store_pixel_u16(&mut self, x: usize, y: usize, rgb: [u16; 3]) -> [u16; 3] {
+        let index = y * self.width() + x;
+        self.pixels[index] = rgb.map(|value| (value >> 8) as u8);
+        let pixel_count = self.pixels.len();
+        self.linear_pixels
+            .get_or_insert_with(|| vec![None; pixel_count])[index] = Some(rgb);
+        self.color_lookup.lookup_linear_rgb(self.brightness, rgb)
+    }
+
+    /// Write the looked up luminance of a pixel into the bit planes, with ordered dithering unless it is
+    /// disabled.
+    #[inline]
+    fn write_luminance(&mut self, x: usize, y: usize, luminance: [u16; 3]) {
+        let dithering = self.spatial_dithering != SpatialDithering::None;
+        let planes = quantize_ordered(dithering, x, y, luminance, self.pwm_bits);
+        self.write_bit_planes(x, y, planes);
+    }
+
+    /// Write the luminance of a pixel, scaled to the bit planes, into the bit planes.
+    #[inline]
+    fn write_bit_planes(&mut self, x: usize, y: usize, planes: [u16; 3]) {
         let designator = self
             .shared_mapper
             .get(x, y)
             .expect("Pixel not in designator map. This is a bug.");
-        let PixelDesignator {
-            gpio_word,
-            r_bit,
-            g_bit,
-            b_bit,
-            mask: designator_mask,
-        } = *designator;
+        write_designated_bit_planes(
+            &mut self.bitplane_buffer,
+            self.cols,
+            self.pwm_bits,
+            designator,
+            planes,
+        );
+    }
 
-        let Some(pos_start) = gpio_word else {
-            // non-used pixel marker.
-            return;
-        };
+    /// Copy a rectangle of packed 8 bit RGB pixels onto the canvas, with its top left corner at `(x, y)`.
+    /// `stride` is the number of bytes from the start of one row of `data` to the next. Pixels outside of
+    /// the canvas are ignored.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is too short for `width`, `height` and `stride`.
+    pub fn blit_rgb(
+        &mut self,
+        data: &[u8],
+        stride: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) {
+        self.blit_pixels(data, stride, [x, y], [width, height], |&[r, g, b], _| {
+            BlitColor::Rgb([r, g, b])
+        });
+    }
 
-        let [red, green, blue] = self.color_lookup.lookup_rgb(self.brightness, r, g, b);
+    /// Like [`Canvas::blit_rgb`], but with the channels in blue, green, red order.
+    pub fn blit_bgr(
+        &mut self,
+        data: &[u8],
+        stride: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) {
+        self.blit_pixels(data, stride, [x, y], [width, height], |&[b, g, r], _| {
+            BlitColor::Rgb([r, g, b])
+        });
+    }
 
-        let min_bit_plane = K_BIT_PLANES - self.pwm_bits;
+    /// Like [`Canvas::blit_rgb`], but with an alpha channel. The pixels are blended over the current content
+    /// of the canvas.
+    pub fn blit_rgba(
+        &mut self,
+        data: &[u8],
+        stride: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) {
+        self.blit_pixels(
+            data,
+            stride,
+            [x, y],
+            [width, height],
+            |&[r, g, b, a], below| {
+                let blend = |source: u8, destination: u8| {
+                    let a = u16::from(a);
+                    ((u16::from(source) * a + u16::from(destination) * (255 - a) + 127) / 255) as u8
+                };
+                BlitColor::Rgb([blend(r, below[0]), blend(g, below[1]), blend(b, below[2])])
+            },
+        );
+    }
 
-        (min_bit_plane..K_BIT_PLANES).for_each(|plane| {
-            let pos = pos_start + self.cols * plane;
-            let mask = 1 << plane;
-            let mut color_bits = 0;
-            if (red & mask) != 0 {
-                color_bits |= r_bit;
-            };
-            if (green & mask) != 0 {
-                color_bits |= g_bit;
-            };
-            if (blue & mask) != 0 {
-                color_bits |= b_bit;
-            };
-            self.bitplane_buffer[pos] &= designator_mask;
-            self.bitplane_buffer[pos] |= color_bits;
+    /// Like [`Canvas::blit_rgb`], but with 16 bit little endian RGB565 pixels.
+    pub fn blit_rgb565(
+        &mut self,
+        data: &[u8],
+        stride: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) {
+        self.blit_pixels(data, stride, [x, y], [width, height], |&bytes, _| {
+            let value = u16::from_le_bytes(bytes);
+            let r = (value >> 11) as u8;
+            let g = ((value >> 5) & 0x3F) as u8;
+            let b = (value & 0x1F) as u8;
+            BlitColor::Rgb([
+                (r << 3) | (r >> 2),
+                (g << 2) | (g >> 4),
+                (b << 3) | (b >> 2),
+            ])
+        });
+    }
+
+    /// Like [`Canvas::blit_rgb`], but with linear 16 bit values per channel as in [`Canvas::set_pixel_u16`].
+    /// `stride` is the number of values from the start of one row of `data` to the next.
+    pub fn blit_rgb_u16(
+        &mut self,
+        data: &[u16],
+        stride: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) {
+        self.blit_pixels(data, stride, [x, y], [width, height], |&rgb, _| {
+            BlitColor::Linear(rgb)
         });
     }
 
+    /// Write a rectangle of pixels with `N` values each, clipped to the canvas. `convert` gets the values of
+    /// every pixel and the color that is currently below it. Each row is first converted to the luminance
+    /// of its bit planes, which are then written plane by plane.
+    fn blit_pixels<T, const N: usize>(
+        &mut self,
+        data: &[T],
+        stride: usize,
+        [x, y]: [usize; 2],
+        [width, height]: [usize; 2],
+        convert: impl Fn(&[T; N], [u8; 3]) -> BlitColor,
+    ) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let required = (height - 1) * stride + width * N;
+        assert!(
+            data.len() >= required,
+            "Blit of {width}x{height} pixels with a stride of {stride} needs {required} values, got {}.",
+            data.len()
+        );
+        let canvas_width = self.width();
+        let columns = width.min(canvas_width.saturating_sub(x));
+        let rows = height.min(self.height().saturating_sub(y));
+        let Self {
+            cols,
+            bitplane_buffer,
+            pixels,
+            linear_pixels,
+            shared_mapper,
+            pwm_bits,
+            brightness,
+            color_lookup,
+            spatial_dithering,
+            ..
+        } = self;
+        let ordered_dithering = *spatial_dithering != SpatialDithering::None;
+        let mut diffusion = (*spatial_dithering == SpatialDithering::FloydSteinberg)
+            .then(|| ErrorDiffusion::new(columns, *pwm_bits));
+        let pixel_count = pixels.len();
+        let mut row_planes = vec![[0; 3]; columns];
+        for row in 0..rows {
+            let line = &data[row * stride..][..columns * N];
+            let start = (y + row) * canvas_width + x;
+            let pixel_row = &mut pixels[start..start + columns];
+            for (column, (values, pixel)) in line.chunks_exact(N).zip(pixel_row).enumerate() {
+                let values = values.try_into().expect("Chunk has N values.");
+                let luminance = match convert(values, *pixel) {
+                    BlitColor::Rgb([r, g, b]) => {
+                        *pixel = [r, g, b];
+                        if let Some(linear_pixels) = linear_pixels {
+                            linear_pixels[start + column] = None;
+                        }
+                        color_lookup.lookup_rgb(*brightness, r, g, b)
+                    }
+                    BlitColor::Linear(rgb) => {
+                        *pixel = rgb.map(|value| (value >> 8) as u8);
+                        linear_pixels.get_or_insert_with(|| vec![None; pixel_count])
+                            [start + column] = Some(rgb);
+                        color_lookup.lookup_linear_rgb(*brightness, rgb)
+                    }
+                };
+                row_planes[column] = match &mut diffusion {
+                    Some(diffusion) => diffusion.quantize(column, luminance),
+                    None => quantize_ordered(
+                        ordered_dithering,
+                        x + column,
+                        y + row,
+                        luminance,
+                        *pwm_bits,
+                    ),
+                };
+            }
+            if let Some(diffusion) = &mut diffusion {
+                diffusion.next_row();
+            }
+
+            let designators = shared_mapper.row(x, y + row, columns);
+            match uniform_designator(designators) {
+                Some(designator) => write_row_bit_planes(
+                    bitplane_buffer,
+                    *cols,
+                    *pwm_bits,
+                    &designator,
+                    &row_planes,
+                ),
+                None => designators
+                    .iter()
+                    .zip(&row_planes)
+                    .for_each(|(designator, planes)| {
+                        write_designated_bit_planes(
+                            bitplane_buffer,
+                            *cols,
+                            *pwm_bits,
+                            designator,
+                            *planes,
+                        );
+                    }),
+            }
+        }
+    }
+
     pub fn fill(&mut self, r: u8, g: u8, b: u8) {
+        if self.spatial_dithering != SpatialDithering::None {
+            // The dither pattern differs from pixel to pixel.
+            let width = self.width();
+            for index in 0..self.pixels.len() {
+                self.write_pixel(index % width, index / width, [r, g, b]);
+            }
+            return;
+        }
+        self.pixels.fill([r, g, b]);
+        self.linear_pixels = None;
         let designator = self.shared_mapper.get_pixel_designator();
         let PixelDesignator {
             r_bit,
@@ -266,7 +570,10 @@ impl Canvas {
             ..
         } = designator;
 
-        let [red, green, blue] = self.color_lookup.lookup_rgb(self.brightness, r, g, b);
+        let [red, green, blue] = self
+            .color_lookup
+            .lookup_rgb(self.brightness, r, g, b)
+            .map(|luminance| luminance >> FRACTION_BITS);
 
         (K_BIT_PLANES - self.pwm_bits..K_BIT_PLANES).for_each(|b| {
             let mask = 1 << b;
@@ -286,11 +593,48 @@ impl Canvas {
         });
     }
 
-    pub(crate) fn dump_to_matrix(
+    /// The color of a pixel as it was set, before the brightness and the luminance correction are applied.
+    /// The coordinates are the visible ones, like in [`Canvas::set_pixel`]. Returns `None` outside of the
+    /// canvas.
+    #[must_use]
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        self.pixels.get(y * self.width() + x).copied()
+    }
+
+    /// The column and the double row of the matrix that a visible pixel is shown in, and the color bits of
+    /// its sub-panel.
+    pub(crate) fn matrix_designation(&self, x: usize, y: usize) -> Option<(usize, usize, u32)> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let designator = self.shared_mapper.get(x, y)?;
+        let offset = designator.gpio_word?;
+        let row_length = self.cols * K_BIT_PLANES;
+        let bits = designator.r_bit | designator.g_bit | designator.b_bit;
+        Some((offset % row_length, offset / row_length, bits))
+    }
+
+    /// The colors of all pixels as they were set, row by row. Pixel `(x, y)` is at index
+    /// `y * width() + x`.
+    #[must_use]
+    pub fn pixels(&self) -> &[[u8; 3]] {
+        &self.pixels
+    }
+
+    /// The colors of all pixels as they were set, row by row, as packed 8 bit RGB.
+    #[must_use]
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+
+    pub(crate) fn dump_to_matrix<B: RegisterBackend>(
         &self,
-        gpio: &mut Gpio,
+        gpio: &mut Gpio<B>,
         hardware_mapping: &HardwareMapping,
-        row_setter: &mut dyn RowAddressSetter,
+        row_setter: &mut dyn RowAddressSetter<B>,
         pwm_low_bit: usize,
         color_clk_mask: u32,
     ) {
@@ -347,6 +691,155 @@ impl Canvas {
     pub fn set_brightness(&mut self, brightness: u8) {
         self.brightness = brightness.clamp(1, 100);
     }
+
+    /// The color lookup the canvas converts colors with.
+    pub(crate) fn color_lookup(&self) -> Arc<ColorLookup> {
+        Arc::clone(&self.color_lookup)
+    }
+
+    /// Make this canvas a copy of `blank`, reusing its buffers.
+    pub(crate) fn reset_to(&mut self, blank: &Canvas) {
+        self.bitplane_buffer.clone_from(&blank.bitplane_buffer);
+        self.pixels.clone_from(&blank.pixels);
+        self.linear_pixels = None;
+        self.pwm_bits = blank.pwm_bits;
+        self.brightness = blank.brightness;
+        self.color_lookup = Arc::clone(&blank.color_lookup);
+        self.spatial_dithering = blank.spatial_dithering;
+        self.interlaced = blank.interlaced;
+        self.reconfiguration_generation = blank.reconfiguration_generation;
+    }
+
+    /// Take over the brightness, PWM bits and color lookup that were reconfigured on the matrix, converting
+    /// the pixels again if they changed. `generation` counts the reconfigurations. A canvas that has already
+    /// taken over the latest one is left alone, so changes made on the canvas afterwards are kept.
+    pub(crate) fn apply_reconfiguration(
+        &mut self,
+        generation: u64,
+        brightness: u8,
+        pwm_bits: usize,
+        color_lookup: &Arc<ColorLookup>,
+    ) {
+        if self.reconfiguration_generation >= generation {
+            return;
+        }
+        self.reconfiguration_generation = generation;
+        if self.brightness == brightness
+            && self.pwm_bits == pwm_bits
+            && Arc::ptr_eq(&self.color_lookup, color_lookup)
+        {
+            return;
+        }
+        self.brightness = brightness;
+        self.pwm_bits = pwm_bits;
+        self.color_lookup = Arc::clone(color_lookup);
+        self.rewrite_pixels();
+    }
+
+    /// Change the spatial dithering for the pixels that are written from now on.
+    pub fn set_spatial_dithering(&mut self, spatial_dithering: SpatialDithering) {
+        self.spatial_dithering = spatial_dithering;
+    }
+
+    /// Convert the colors of all pixels again, e.g. after the color lookup changed. The whole canvas is
+    /// written like one blit, so it keeps the spatial dithering, including Floyd-Steinberg error diffusion.
+    fn rewrite_pixels(&mut self) {
+        let colors: Vec<BlitColor> = (0..self.pixels.len())
+            .map(
+                |index| match self.linear_pixels.as_ref().and_then(|linear| linear[index]) {
+                    Some(rgb) => BlitColor::Linear(rgb),
+                    None => BlitColor::Rgb(self.pixels[index]),
+                },
+            )
+            .collect();
+        let (width, height) = (self.width(), self.height());
+        self.blit_pixels(&colors, width, [0, 0], [width, height], |&[color], _| color);
+    }
+}
+
+/// The designator of the first pixel of a row if it applies to the whole row: the pixels are shown in
+/// consecutive columns of the same sub-panel, like without pixel mappers.
+fn uniform_designator(designators: &[PixelDesignator]) -> Option<PixelDesignator> {
+    let first = *designators.first()?;
+    let start = first.gpio_word?;
+    designators
+        .iter()
+        .enumerate()
+        .all(|(index, designator)| {
+            designator.gpio_word == Some(start + index) && designator.mask == first.mask
+        })
+        .then_some(first)
+}
+
+/// Write the luminance of a row of pixels, scaled to the bit planes, into the bit planes. The pixels are
+/// shown from the position of `designator` on, with its color bits. This writes every bit plane of the row
+/// as one contiguous run of words.
+fn write_row_bit_planes(
+    bitplane_buffer: &mut [u32],
+    cols: usize,
+    pwm_bits: usize,
+    designator: &PixelDesignator,
+    row_planes: &[[u16; 3]],
+) {
+    let PixelDesignator {
+        gpio_word,
+        r_bit,
+        g_bit,
+        b_bit,
+        mask: designator_mask,
+    } = *designator;
+
+    let Some(pos_start) = gpio_word else {
+        return;
+    };
+
+    (K_BIT_PLANES - pwm_bits..K_BIT_PLANES).for_each(|plane| {
+        let start = pos_start + cols * plane;
+        let words = &mut bitplane_buffer[start..start + row_planes.len()];
+        words
+            .iter_mut()
+            .zip(row_planes)
+            .for_each(|(word, [red, green, blue])| {
+                let color_bits = ((u32::from(red >> plane) & 1) * r_bit)
+                    | ((u32::from(green >> plane) & 1) * g_bit)
+                    | ((u32::from(blue >> plane) & 1) * b_bit);
+                *word = (*word & designator_mask) | color_bits;
+            });
+    });
+}
+
+/// Write the luminance of a pixel, scaled to the bit planes, into the bit planes at its designator.
+#[inline]
+fn write_designated_bit_planes(
+    bitplane_buffer: &mut [u32],
+    cols: usize,
+    pwm_bits: usize,
+    designator: &PixelDesignator,
+    [red, green, blue]: [u16; 3],
+) {
+    let PixelDesignator {
+        gpio_word,
+        r_bit,
+        g_bit,
+        b_bit,
+        mask: designator_mask,
+    } = *designator;
+
+    let Some(pos_start) = gpio_word else {
+        // non-used pixel marker.
+        return;
+    };
+
+    let min_bit_plane = K_BIT_PLANES - pwm_bits;
+
+    (min_bit_plane..K_BIT_PLANES).for_each(|plane| {
+        let pos = pos_start + cols * plane;
+        let color_bits = ((u32::from(red >> plane) & 1) * r_bit)
+            | ((u32::from(green >> plane) & 1) * g_bit)
+            | ((u32::from(blue >> plane) & 1) * b_bit);
+        let word = &mut bitplane_buffer[pos];
+        *word = (*word & designator_mask) | color_bits;
+    });
 }
 
 #[cfg(feature = "drawing")]
@@ -398,3 +891,194 @@ pub mod embedded_graphics_support {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        color::ColorLookup,
+        simulated_backend::test_support::{simulated_matrix, test_config},
+        ColorCorrection, MultiplexMapperType, RGBMatrixConfig, Reconfiguration, SpatialDithering,
+        WhiteBalance,
+    };
+
+    #[test]
+    fn test_pixel_readback() {
+        let (_matrix, mut canvas) = simulated_matrix(RGBMatrixConfig {
+            rows: 32,
+            cols: 32,
+            multiplexing: Some(MultiplexMapperType::Stripe),
+            pixelmapper: vec!["Rotate:90".parse().unwrap()],
+            ..test_config()
+        });
+        canvas.fill(1, 2, 3);
+        canvas.set_pixel(31, 5, 200, 100, 50);
+        canvas.set_pixel(32, 5, 255, 255, 255);
+        assert_eq!(canvas.get_pixel(31, 5), Some([200, 100, 50]));
+        assert_eq!(canvas.get_pixel(0, 0), Some([1, 2, 3]));
+        assert_eq!(canvas.get_pixel(32, 0), None);
+        let pixels = canvas.pixels();
+        assert_eq!(pixels.len(), canvas.width() * canvas.height());
+        assert_eq!(pixels[5 * canvas.width() + 31], [200, 100, 50]);
+        let bytes = canvas.to_rgb_bytes();
+        assert_eq!(&bytes[..3], [1, 2, 3]);
+        assert_eq!(bytes.len(), 3 * pixels.len());
+    }
+
+    #[test]
+    fn test_blit() {
+        let (_matrix, mut blitted) = simulated_matrix(RGBMatrixConfig {
+            chain_length: 2,
+            ..test_config()
+        });
+        blitted.fill(10, 20, 30);
+        let mut expected = blitted.clone();
+
+        // A 4x3 rectangle with a stride of 16 bytes that sticks out to the right of the canvas.
+        let (x, y, width, height, stride) = (62, 5, 4, 3, 16);
+        let rgb: Vec<u8> = (0..stride * height).map(|i| (i * 37 % 256) as u8).collect();
+        blitted.blit_rgb(&rgb, stride, x, y, width, height);
+        for row in 0..height {
+            for column in 0..width {
+                let [r, g, b] = rgb[row * stride + column * 3..][..3] else {
+                    unreachable!()
+                };
+                expected.set_pixel(x + column, y + row, r, g, b);
+            }
+        }
+        assert_eq!(blitted.pixels, expected.pixels);
+        assert_eq!(blitted.bitplane_buffer, expected.bitplane_buffer);
+
+        let mut bgr = rgb.clone();
+        bgr.chunks_exact_mut(3).for_each(|pixel| pixel.reverse());
+        blitted.fill(10, 20, 30);
+        blitted.blit_bgr(&bgr[..15], 15, 60, 0, 5, 1);
+        blitted.blit_rgb(&rgb[..15], 15, 60, 1, 5, 1);
+        assert_eq!(blitted.pixels[60..64], blitted.pixels[64 + 60..128]);
+
+        blitted.blit_rgba(
+            &[255, 0, 0, 0, 0, 255, 0, 255, 100, 100, 100, 128],
+            12,
+            0,
+            0,
+            3,
+            1,
+        );
+        assert_eq!(blitted.get_pixel(0, 0), Some([10, 20, 30]));
+        assert_eq!(blitted.get_pixel(1, 0), Some([0, 255, 0]));
+        assert_eq!(blitted.get_pixel(2, 0), Some([55, 60, 65]));
+
+        let rgb565 = [0xFF, 0xFF, 0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00];
+        blitted.blit_rgb565(&rgb565, 8, 0, 0, 4, 1);
+        assert_eq!(
+            blitted.pixels[..4],
+            [[255, 255, 255], [255, 0, 0], [0, 255, 0], [0, 0, 255]]
+        );
+
+        // With pixel mappers, the pixels of a row are not next to each other on the panels.
+        let (_matrix, mut mapped) = simulated_matrix(RGBMatrixConfig {
+            rows: 32,
+            cols: 32,
+            multiplexing: Some(MultiplexMapperType::Stripe),
+            pixelmapper: vec!["Rotate:90".parse().unwrap()],
+            ..test_config()
+        });
+        let mut expected = mapped.clone();
+        mapped.blit_rgb(&rgb, stride, 3, 5, width, height);
+        for row in 0..height {
+            for column in 0..width {
+                let [r, g, b] = rgb[row * stride + column * 3..][..3] else {
+                    unreachable!()
+                };
+                expected.set_pixel(3 + column, 5 + row, r, g, b);
+            }
+        }
+        assert_eq!(mapped.bitplane_buffer, expected.bitplane_buffer);
+    }
+
+    #[test]
+    fn test_pixel_u16() {
+        let (_matrix, mut canvas) = simulated_matrix(test_config());
+        let blank = canvas.bitplane_buffer.clone();
+
+        // The darkest 8 bit color is switched off by the CIE1931 correction, a linear value is not.
+        canvas.set_pixel(3, 3, 1, 1, 1);
+        assert_eq!(canvas.bitplane_buffer, blank);
+        assert!(canvas.linear_pixels.is_none());
+        canvas.set_pixel_u16(3, 3, 32, 32, 32);
+        assert_ne!(canvas.bitplane_buffer, blank);
+        assert_eq!(canvas.get_pixel(3, 3), Some([0, 0, 0]));
+
+        // Converting the pixels again keeps the linear value.
+        let written = canvas.bitplane_buffer.clone();
+        let color_lookup = Arc::new(ColorLookup::new(
+            &ColorCorrection::default(),
+            WhiteBalance::default(),
+        ));
+        canvas.apply_reconfiguration(1, 100, 11, &color_lookup);
+        assert_eq!(canvas.bitplane_buffer, written);
+
+        let mut blitted = canvas.clone();
+        let data: Vec<u16> = (0..3 * 4 * 2).map(|i| i as u16 * 2731).collect();
+        blitted.blit_rgb_u16(&data, 12, 30, 10, 4, 2);
+        for row in 0..2 {
+            for column in 0..4 {
+                let [r, g, b] = data[row * 12 + column * 3..][..3] else {
+                    unreachable!()
+                };
+                canvas.set_pixel_u16(30 + column, 10 + row, r, g, b);
+            }
+        }
+        assert_eq!(blitted.pixels, canvas.pixels);
+        assert_eq!(blitted.linear_pixels, canvas.linear_pixels);
+        assert_eq!(blitted.bitplane_buffer, canvas.bitplane_buffer);
+        assert_eq!(canvas.get_pixel(31, 11), Some([160, 170, 181]));
+
+        canvas.fill(0, 0, 0);
+        assert!(canvas.linear_pixels.is_none());
+    }
+
+    #[test]
+    fn test_reconfiguration_generation() {
+        let (mut matrix, mut canvas) = simulated_matrix(test_config());
+        let color_lookup = canvas.color_lookup();
+        // All canvases share the color lookup.
+        assert!(Arc::ptr_eq(
+            &color_lookup,
+            &matrix.create_offscreen_canvas().color_lookup
+        ));
+        canvas.set_brightness(50);
+        // Nothing was reconfigured on the matrix.
+        canvas.apply_reconfiguration(0, 100, 11, &color_lookup);
+        assert_eq!(canvas.brightness, 50);
+
+        canvas.apply_reconfiguration(1, 80, 11, &color_lookup);
+        assert_eq!(canvas.brightness, 80);
+        // The canvas already took over the reconfiguration.
+        canvas.set_brightness(30);
+        canvas.apply_reconfiguration(1, 80, 11, &color_lookup);
+        assert_eq!(canvas.brightness, 30);
+    }
+
+    #[test]
+    fn test_reconfiguration_keeps_dithering() {
+        let (mut matrix, mut canvas) = simulated_matrix(RGBMatrixConfig {
+            spatial_dithering: SpatialDithering::FloydSteinberg,
+            ..test_config()
+        });
+        let data: Vec<u8> = (0..32 * 16 * 3).map(|i| (i * 7 % 256) as u8).collect();
+        canvas.blit_rgb(&data, 32 * 3, 0, 0, 32, 16);
+        let diffused = canvas.bitplane_buffer.clone();
+        // The canvas is converted again with an equal color lookup.
+        matrix
+            .reconfigure(Reconfiguration::WhiteBalance(WhiteBalance::default()))
+            .unwrap();
+        matrix.apply_reconfiguration(&mut canvas);
+        assert!(Arc::ptr_eq(
+            &canvas.color_lookup,
+            &matrix.create_offscreen_canvas().color_lookup
+        ));
+        assert_eq!(canvas.bitplane_buffer, diffused);
+    }
+}
@@ -1,4 +1,9 @@
-use std::{error::Error, fs::read_to_string, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    fs::read_to_string,
+    str::FromStr,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PiChip {
@@ -8,6 +13,8 @@ pub enum PiChip {
     BCM2709,
     /// Model 4
     BCM2711,
+    /// Model 5, with the GPIO pins on the RP1 southbridge
+    BCM2712,
 }
 
 impl FromStr for PiChip {
@@ -18,11 +25,19 @@ impl FromStr for PiChip {
             "BCM2708" | "BCM2835" => Ok(Self::BCM2708),
             "BCM2709" | "BCM2836" | "BCM2837" => Ok(Self::BCM2709),
             "BCM2711" => Ok(Self::BCM2711),
+            "BCM2712" => Ok(Self::BCM2712),
             _ => Err(format!("'{s}' is not a valid chip model.").into()),
         }
     }
 }
 
+impl Display for PiChip {
+    /// The name as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 impl PiChip {
     /// Try to automatically determine the model.
     #[must_use]
@@ -59,6 +74,8 @@ impl PiChip {
             2 => Some(Self::BCM2709),
             // BCM2711
             3 => Some(Self::BCM2711),
+            // BCM2712
+            4 => Some(Self::BCM2712),
             _ => None,
         }
     }
@@ -66,22 +83,27 @@ impl PiChip {
     pub(crate) const fn num_cores(self) -> usize {
         match self {
             PiChip::BCM2708 => 1,
-            PiChip::BCM2709 | PiChip::BCM2711 => 4,
+            PiChip::BCM2709 | PiChip::BCM2711 | PiChip::BCM2712 => 4,
         }
     }
 
     // All peripherals can be described by an offset from the Peripheral Base Address.
+    // On the BCM2712, this is the base of the RP1 peripherals behind the PCIe bus.
     pub(crate) const fn get_peripherals_base(self) -> u64 {
         match self {
             PiChip::BCM2708 => 0x2000_0000,
             PiChip::BCM2709 => 0x3F00_0000,
             PiChip::BCM2711 => 0xFE00_0000,
+            PiChip::BCM2712 => 0x1F_0000_0000,
         }
     }
 
     pub(crate) fn gpio_slowdown(self) -> u32 {
         match self {
             PiChip::BCM2708 | PiChip::BCM2709 => 1,
+            // The CPU is even faster than the BCM2711, but writes to the RP1 have to cross the PCIe bus,
+            // which already slows them down to about the speed of the older chips.
+            PiChip::BCM2712 => 1,
             PiChip::BCM2711 => 3,
         }
     }
@@ -1,39 +1,412 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
 use crate::config::K_BIT_PLANES;
 
+/// How the 8 bit color values are converted to the luminance of the LEDs. LEDs respond linearly to the
+/// time they are switched on, so without a correction, dark colors look much too bright.
+///
+/// These options can be used with the `--color-correction` flag.
+#[derive(Clone, Debug, Default)]
+pub enum ColorCorrection {
+    /// The CIE1931 lightness curve.
+    #[default]
+    Cie1931,
+    /// A power law with the given exponent, e.g. `Gamma:2.2`.
+    Gamma(f32),
+    /// No correction, for content that is already corrected.
+    Identity,
+    /// One table per channel in red, green, blue order, mapping the color value to the relative luminance
+    /// where `u16::MAX` is fully on. As a string, the 256 comma separated values of a table that is used for
+    /// all channels, or three tables separated by `/`, e.g. `Lut:0,1,4,...`.
+    Lut(Box<[[u16; 256]; 3]>),
+}
+
+impl PartialEq for ColorCorrection {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Gamma(a), Self::Gamma(b)) => a.to_bits() == b.to_bits(),
+            (Self::Lut(a), Self::Lut(b)) => a == b,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl Eq for ColorCorrection {}
+
+impl Hash for ColorCorrection {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Gamma(exponent) => exponent.to_bits().hash(state),
+            Self::Lut(tables) => tables.hash(state),
+            Self::Cie1931 | Self::Identity => {}
+        }
+    }
+}
+
+fn parse_table(s: &str) -> Result<[u16; 256], Box<dyn Error>> {
+    let values = s
+        .split(',')
+        .map(|value| value.trim().parse::<u16>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid lookup table value: {e}"))?;
+    let count = values.len();
+    values
+        .try_into()
+        .map_err(|_| format!("A lookup table needs 256 values, got {count}.").into())
+}
+
+impl FromStr for ColorCorrection {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("CIE1931") => Ok(Self::Cie1931),
+            None if s.eq_ignore_ascii_case("Identity") => Ok(Self::Identity),
+            Some(("Gamma", exponent)) => match exponent.parse::<f32>() {
+                Ok(exponent) if exponent.is_finite() && exponent > 0.0 => Ok(Self::Gamma(exponent)),
+                _ => Err(format!("'{exponent}' is not a valid gamma exponent.").into()),
+            },
+            Some(("Lut", tables)) => {
+                let tables = tables
+                    .split('/')
+                    .map(parse_table)
+                    .collect::<Result<Vec<_>, _>>()?;
+                match tables[..] {
+                    [table] => Ok(Self::Lut(Box::new([table; 3]))),
+                    [red, green, blue] => Ok(Self::Lut(Box::new([red, green, blue]))),
+                    _ => Err("Either one or three lookup tables are required.".into()),
+                }
+            }
+            _ => Err(format!("'{s}' is not a valid color correction.").into()),
+        }
+    }
+}
+
+impl Display for ColorCorrection {
+    /// The correction as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorCorrection::Cie1931 => f.write_str("CIE1931"),
+            ColorCorrection::Gamma(exponent) => write!(f, "Gamma:{exponent}"),
+            ColorCorrection::Identity => f.write_str("Identity"),
+            ColorCorrection::Lut(tables) => {
+                let [red, green, blue] = tables.as_ref();
+                let tables: &[[u16; 256]] = if red == green && green == blue {
+                    &tables[..1]
+                } else {
+                    &tables[..]
+                };
+                f.write_str("Lut:")?;
+                for (index, table) in tables.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str("/")?;
+                    }
+                    let values: Vec<String> = table.iter().map(u16::to_string).collect();
+                    f.write_str(&values.join(","))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Scale factors for the luminance of the red, green and blue LEDs, to compensate for LEDs that differ in
+/// efficiency. The factors are between 0 and 1.
+///
+/// These options can be used with the `--white-balance` flag, either as factors like `1,0.8,0.9` or as a
+/// color temperature like `5000K`, see [`WhiteBalance::from_color_temperature`].
+#[derive(Clone, Copy, Debug)]
+pub struct WhiteBalance {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Default for WhiteBalance {
+    fn default() -> Self {
+        Self {
+            red: 1.0,
+            green: 1.0,
+            blue: 1.0,
+        }
+    }
+}
+
+impl PartialEq for WhiteBalance {
+    fn eq(&self, other: &Self) -> bool {
+        self.factors().map(f32::to_bits) == other.factors().map(f32::to_bits)
+    }
+}
+
+impl Eq for WhiteBalance {}
+
+impl Hash for WhiteBalance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.factors().map(f32::to_bits).hash(state);
+    }
+}
+
+impl WhiteBalance {
+    /// The white balance that makes full white look like a black body of the given temperature, from 1000
+    /// to 40000 Kelvin. The colors follow Tanner Helland's approximation, which is neutral at 6600 Kelvin.
+    #[must_use]
+    pub fn from_color_temperature(kelvin: u16) -> Self {
+        let t = f32::from(kelvin.clamp(1000, 40000)) / 100.0;
+        let red = if t <= 66.0 {
+            255.0
+        } else {
+            329.698_73 * (t - 60.0).powf(-0.133_204_76)
+        };
+        let green = if t <= 66.0 {
+            99.470_8 * t.ln() - 161.119_57
+        } else {
+            288.122_17 * (t - 60.0).powf(-0.075_514_85)
+        };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_73 * (t - 10.0).ln() - 305.044_8
+        };
+        // The approximation gives gamma encoded sRGB values, the factors scale the linear luminance.
+        let [red, green, blue] =
+            [red, green, blue].map(|value| (value.clamp(0.0, 255.0) / 255.0).powf(2.2));
+        let max = red.max(green).max(blue);
+        Self {
+            red: red / max,
+            green: green / max,
+            blue: blue / max,
+        }
+    }
+
+    fn factors(self) -> [f32; 3] {
+        [self.red, self.green, self.blue]
+    }
+}
+
+impl FromStr for WhiteBalance {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(kelvin) = s.strip_suffix(['K', 'k']) {
+            return match kelvin.parse::<u16>() {
+                Ok(kelvin) if (1000..=40000).contains(&kelvin) => {
+                    Ok(Self::from_color_temperature(kelvin))
+                }
+                _ => Err(format!(
+                    "'{s}' is not a valid color temperature. It should be between 1000K and 40000K."
+                )
+                .into()),
+            };
+        }
+        let factors = s
+            .split(',')
+            .map(|factor| factor.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid white balance factor: {e}"))?;
+        match factors[..] {
+            [red, green, blue] if factors.iter().all(|f| (0.0..=1.0).contains(f)) => {
+                Ok(Self { red, green, blue })
+            }
+            _ => Err(format!(
+                "'{s}' is not a valid white balance. It should be three factors between 0 and 1, e.g. \
+                 '1,0.8,0.9', or a color temperature, e.g. '5000K'."
+            )
+            .into()),
+        }
+    }
+}
+
+impl Display for WhiteBalance {
+    /// The white balance factors as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}", self.red, self.green, self.blue)
+    }
+}
+
+/// The luminance values of the lookup have this many bits below the lowest bit plane, which are only shown
+/// with spatial dithering.
+pub(crate) const FRACTION_BITS: usize = 4;
+const FRACTION_SCALE: f32 = (1 << FRACTION_BITS) as f32;
+
 // Do CIE1931 luminance correction and scale to output bitplanes
-fn luminance_cie1931(c: u8, brightness: u8) -> u16 {
+fn luminance_cie1931(c: u8, brightness: u8) -> f32 {
     let out_factor = ((1 << K_BIT_PLANES) - 1) as f32;
     let v = f32::from(c) * f32::from(brightness) / 255.0;
-    (out_factor
+    out_factor
         * (if v <= 8.0 {
             v / 902.3
         } else {
             ((v + 16.0) / 116.0).powi(3)
-        })) as u16
+        })
+}
+
+/// Invert the CIE1931 luminance correction at full brightness. Takes the relative luminance in the range
+/// 0 to 1.
+pub(crate) fn inverse_luminance_cie1931(luminance: f32) -> u8 {
+    let luminance = luminance.clamp(0.0, 1.0);
+    let v = if luminance <= 8.0 / 902.3 {
+        luminance * 902.3
+    } else {
+        116.0 * luminance.cbrt() - 16.0
+    };
+    (v * 255.0 / 100.0).round() as u8
+}
+
+impl ColorCorrection {
+    /// The luminance of a channel, scaled to the output bitplanes.
+    fn luminance(&self, channel: usize, c: u8, brightness: u8) -> f32 {
+        let out_factor = ((1 << K_BIT_PLANES) - 1) as f32;
+        let relative = match self {
+            ColorCorrection::Cie1931 => return luminance_cie1931(c, brightness),
+            ColorCorrection::Gamma(exponent) => (f32::from(c) / 255.0).powf(*exponent),
+            ColorCorrection::Identity => f32::from(c) / 255.0,
+            ColorCorrection::Lut(tables) => {
+                f32::from(tables[channel][usize::from(c)]) / f32::from(u16::MAX)
+            }
+        };
+        out_factor * relative * f32::from(brightness) / 100.0
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct ColorLookup {
-    per_brightness: [[u16; 256]; 100],
+    /// The red, green and blue tables for every brightness from 1 to 100.
+    per_brightness: Vec<[[u16; 256]; 3]>,
+    white_balance: WhiteBalance,
 }
 
 impl ColorLookup {
-    pub(crate) fn new_cie1931() -> Self {
-        let mut per_brightness = [[0; 256]; 100];
-        (0..=255u8).for_each(|c| {
-            (0..100u8).for_each(|b| {
-                per_brightness[usize::from(b)][usize::from(c)] = luminance_cie1931(c, b + 1);
-            });
-        });
-        Self { per_brightness }
+    pub(crate) fn new(correction: &ColorCorrection, white_balance: WhiteBalance) -> Self {
+        let per_brightness = (1..=100u8)
+            .map(|brightness| {
+                let mut tables = [[0; 256]; 3];
+                for ((channel, table), factor) in
+                    tables.iter_mut().enumerate().zip(white_balance.factors())
+                {
+                    (0..=255u8).for_each(|c| {
+                        let luminance = correction.luminance(channel, c, brightness);
+                        table[usize::from(c)] = (luminance * factor * FRACTION_SCALE) as u16;
+                    });
+                }
+                tables
+            })
+            .collect();
+        Self {
+            per_brightness,
+            white_balance,
+        }
     }
 
+    /// The luminance of a color, scaled to the output bitplanes with [`FRACTION_BITS`] additional bits.
     pub(crate) fn lookup_rgb(&self, brightness: u8, r: u8, g: u8, b: u8) -> [u16; 3] {
-        let for_brightness = &self.per_brightness[brightness as usize - 1];
-        [
-            for_brightness[r as usize],
-            for_brightness[g as usize],
-            for_brightness[b as usize],
-        ]
+        let [red, green, blue] = &self.per_brightness[brightness as usize - 1];
+        [red[r as usize], green[g as usize], blue[b as usize]]
+    }
+
+    /// Scale linear 16 bit values like [`ColorLookup::lookup_rgb`], without the color correction.
+    pub(crate) fn lookup_linear_rgb(&self, brightness: u8, rgb: [u16; 3]) -> [u16; 3] {
+        let out_factor = ((1 << K_BIT_PLANES) - 1) as f32;
+        let scale = out_factor * f32::from(brightness) / 100.0 / f32::from(u16::MAX);
+        let mut luminance = [0; 3];
+        for ((out, value), factor) in luminance
+            .iter_mut()
+            .zip(rgb)
+            .zip(self.white_balance.factors())
+        {
+            *out = (f32::from(value) * scale * factor * FRACTION_SCALE).round() as u16;
+        }
+        luminance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorCorrection, ColorLookup, WhiteBalance, FRACTION_BITS};
+
+    /// Drop the fraction bits of a looked up luminance.
+    fn planes(luminance: [u16; 3]) -> [u16; 3] {
+        luminance.map(|value| value >> FRACTION_BITS)
+    }
+
+    #[test]
+    fn test_color_correction() {
+        let identity = ColorLookup::new(&ColorCorrection::Identity, WhiteBalance::default());
+        assert_eq!(
+            planes(identity.lookup_rgb(100, 255, 128, 0)),
+            [2047, 1027, 0]
+        );
+        assert_eq!(planes(identity.lookup_rgb(50, 255, 0, 0)), [1023, 0, 0]);
+
+        let gamma = ColorLookup::new(&"Gamma:2.2".parse().unwrap(), WhiteBalance::default());
+        assert_eq!(planes(gamma.lookup_rgb(100, 255, 128, 0)), [2047, 449, 0]);
+
+        let cie = ColorLookup::new(&ColorCorrection::default(), WhiteBalance::default());
+        assert_eq!(planes(cie.lookup_rgb(100, 255, 128, 0)), [2047, 380, 0]);
+
+        let mut red = [0; 256];
+        red[255] = u16::MAX;
+        let green: [u16; 256] = std::array::from_fn(|c| c as u16 * 257);
+        let lut = ColorCorrection::Lut(Box::new([red, green, [u16::MAX; 256]]));
+        let lookup = ColorLookup::new(&lut, WhiteBalance::default());
+        assert_eq!(planes(lookup.lookup_rgb(100, 128, 255, 0)), [0, 2047, 2047]);
+        assert_eq!(lut.to_string().parse::<ColorCorrection>().unwrap(), lut);
+
+        let single = ColorCorrection::Lut(Box::new([green; 3]));
+        assert_eq!(single.to_string().matches('/').count(), 0);
+        assert_eq!(
+            single.to_string().parse::<ColorCorrection>().unwrap(),
+            single
+        );
+
+        assert!("Gamma:0".parse::<ColorCorrection>().is_err());
+        assert!("Lut:1,2,3".parse::<ColorCorrection>().is_err());
+    }
+
+    #[test]
+    fn test_white_balance() {
+        let balance: WhiteBalance = "1,0.5,0.25".parse().unwrap();
+        let lookup = ColorLookup::new(&ColorCorrection::Identity, balance);
+        assert_eq!(
+            planes(lookup.lookup_rgb(100, 255, 255, 255)),
+            [2047, 1023, 511]
+        );
+        assert_eq!(
+            balance.to_string().parse::<WhiteBalance>().unwrap(),
+            balance
+        );
+
+        let neutral: WhiteBalance = "6600K".parse().unwrap();
+        assert_eq!(neutral, WhiteBalance::default());
+        let warm = WhiteBalance::from_color_temperature(3000);
+        assert_eq!(warm.red, 1.0);
+        assert!(warm.green < 1.0 && warm.blue < warm.green);
+        let cold = WhiteBalance::from_color_temperature(10000);
+        assert_eq!(cold.blue, 1.0);
+        assert!(cold.red < 1.0);
+
+        assert!("1,2,1".parse::<WhiteBalance>().is_err());
+        assert!("1,1".parse::<WhiteBalance>().is_err());
+        assert!("500K".parse::<WhiteBalance>().is_err());
+    }
+
+    #[test]
+    fn test_linear_lookup() {
+        let lookup = ColorLookup::new(&ColorCorrection::default(), "1,0.5,1".parse().unwrap());
+        assert_eq!(
+            lookup.lookup_linear_rgb(100, [u16::MAX, u16::MAX, 32]),
+            [2047 << 4, 2047 << 3, 16]
+        );
+        assert_eq!(
+            lookup.lookup_linear_rgb(50, [u16::MAX, 0, 0]),
+            [2047 << 3, 0, 0]
+        );
     }
 }
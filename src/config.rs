@@ -1,8 +1,15 @@
-use argh::FromArgs;
+use argh::{EarlyExit, FromArgs};
 
 use crate::{
-    canvas::LedSequence, init_sequence::PanelType, multiplex_mapper::MultiplexMapperType,
-    named_pixel_mapper::NamedPixelMapperType, row_address_setter::RowAddressSetterType,
+    canvas::LedSequence,
+    color::{ColorCorrection, WhiteBalance},
+    dithering::SpatialDithering,
+    init_sequence::PanelType,
+    multiplex_mapper::MultiplexMapperType,
+    named_pixel_mapper::NamedPixelMapperType,
+    privileges::PrivilegeDrop,
+    row_address_setter::RowAddressSetterType,
+    system_tuning::ThreadScheduling,
     HardwareMapping, PiChip,
 };
 
@@ -16,9 +23,20 @@ pub(crate) const SUB_PANELS: usize = 2;
 pub(crate) const K_BIT_PLANES: usize = 11;
 
 /// Configuration for an RGB matrix panel controller.
+///
+/// With the `serde` feature, the configuration can be (de)serialized, e.g. to load it from a TOML or JSON
+/// file. Values are written like the command line flags and missing fields take their default value.
+/// [`RGBMatrixConfig::from_env_with_base`] merges such a configuration with the command line.
 #[derive(FromArgs, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct RGBMatrixConfig {
-    /// the display wiring e.g. "Regular", "AdafruitHat", "AdafruitHatPwm", etc. Default: "Regular"
+    /// the display wiring e.g. "Regular", "AdafruitHat", "AdafruitHatPwm", etc. or a pin specification like
+    /// "oe=18,clk=17,lat=4,a=22,b=23,c=24,p0.r1=11,p0.g1=27,p0.b1=7,p0.r2=8,p0.g2=9,p0.b2=10".
+    /// Default: "Regular"
     #[argh(option, default = "HardwareMapping::regular()")]
     pub hardware_mapping: HardwareMapping,
     /// the number of display rows. Default: 64
@@ -92,12 +110,150 @@ pub struct RGBMatrixConfig {
     /// brightness in percent. Default: 100
     #[argh(option, default = "100")]
     pub led_brightness: u8,
+    /// how color values are converted to LED luminance. Either "CIE1931", "Gamma:<exponent>", "Identity" or
+    /// "Lut:<256 comma separated values>" with an optional second and third table separated by "/" for
+    /// green and blue. Default: "CIE1931"
+    #[argh(option, default = "ColorCorrection::Cie1931")]
+    pub color_correction: ColorCorrection,
+    /// scale factors for the red, green and blue LEDs between 0 and 1, e.g. "1,0.8,0.9", or a color
+    /// temperature of white like "5000K". Default: "1,1,1"
+    #[argh(option, default = "WhiteBalance::default()")]
+    pub white_balance: WhiteBalance,
+    /// how the luminance of a pixel is reduced to the shown bit planes when it is written to the canvas.
+    /// Either "None", "Bayer" for ordered dithering or "FloydSteinberg" for error diffusion on blits.
+    /// Default: "None"
+    #[argh(option, default = "SpatialDithering::None")]
+    pub spatial_dithering: SpatialDithering,
+    /// the CPU core the update thread runs on. Default: the last core
+    #[argh(option)]
+    pub update_thread_core: Option<usize>,
+    /// pin the update thread to its core. Default: true
+    #[argh(option, default = "true")]
+    pub pin_update_thread: bool,
+    /// disable the realtime throttling of the kernel while the matrix is running. It stays disabled if
+    /// the privileges are dropped to another user. Default: true
+    #[argh(option, default = "true")]
+    pub disable_rt_throttling: bool,
+    /// set the core of the update thread to the performance CPU governor while the matrix is running.
+    /// Default: true
+    #[argh(option, default = "true")]
+    pub performance_governor: bool,
+    /// the scheduling of the update thread. Either "MaxPriority" for the highest priority of the current
+    /// policy, "Fifo:<priority>" or "RoundRobin:<priority>" for a realtime policy with a priority between 1
+    /// and 99, or "Unchanged". Default: "MaxPriority"
+    #[argh(option, default = "ThreadScheduling::MaxPriority")]
+    pub update_thread_scheduling: ThreadScheduling,
+    /// blank the panels when no new canvas was passed to the matrix for this many milliseconds, e.g.
+    /// because the program hangs. Default: no watchdog
+    #[argh(option)]
+    pub watchdog_timeout_ms: Option<u64>,
+    /// give up root privileges once the hardware is initialized. Either "user", "user:group" or
+    /// "capabilities" to keep the user but shed all capabilities. Default: keep privileges
+    #[argh(option)]
+    pub drop_privileges: Option<PrivilegeDrop>,
+    /// render the canvas in the terminal instead of driving the GPIO pins, e.g. to try out a program
+    /// without a Raspberry Pi. Default: false
+    #[argh(option, default = "false")]
+    pub preview: bool,
+}
+
+/// Whether the option of `field` is in `args`, either as `--flag value` or as `--flag=value`.
+fn option_given(args: &[&str], field: &str) -> bool {
+    let flag = format!("--{}", field.replace('_', "-"));
+    args.iter().any(|arg| {
+        arg.strip_prefix(flag.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+    })
+}
+
+/// Take the fields that were given on the command line from `cli` and all others from `base`.
+macro_rules! merge_given_fields {
+    ($cli:ident, $base:ident, $is_given:ident, $($field:ident),* $(,)?) => {
+        RGBMatrixConfig {
+            $($field: if $is_given(stringify!($field)) { $cli.$field } else { $base.$field },)*
+        }
+    };
 }
 
 impl RGBMatrixConfig {
     pub(crate) const fn double_rows(&self) -> usize {
         self.rows / SUB_PANELS
     }
+
+    /// Parse the command line arguments like [`FromArgs::from_args`], but take the values of all options
+    /// that are not given from `base` instead of their defaults. A repeated option like `--pixelmapper`
+    /// replaces the whole list of `base`.
+    pub fn from_args_with_base(
+        base: Self,
+        command_name: &[&str],
+        args: &[&str],
+    ) -> Result<Self, EarlyExit> {
+        let cli = Self::from_args(command_name, args)?;
+        let is_given = |field: &str| option_given(args, field);
+        Ok(merge_given_fields!(
+            cli,
+            base,
+            is_given,
+            hardware_mapping,
+            rows,
+            cols,
+            refresh_rate,
+            pi_chip,
+            pwm_bits,
+            pwm_lsb_nanoseconds,
+            slowdown,
+            interlaced,
+            dither_bits,
+            chain_length,
+            parallel,
+            panel_type,
+            multiplexing,
+            pixelmapper,
+            row_setter,
+            led_sequence,
+            led_brightness,
+            color_correction,
+            white_balance,
+            spatial_dithering,
+            update_thread_core,
+            pin_update_thread,
+            disable_rt_throttling,
+            performance_governor,
+            update_thread_scheduling,
+            watchdog_timeout_ms,
+            drop_privileges,
+            preview,
+        ))
+    }
+
+    /// Create the configuration from the arguments of the current process like [`argh::from_env`], with
+    /// the values that are not given taken from `base`, see [`RGBMatrixConfig::from_args_with_base`].
+    ///
+    /// This exits the process if the arguments are invalid or `--help` was requested.
+    #[must_use]
+    pub fn from_env_with_base(base: Self) -> Self {
+        let strings: Vec<String> = std::env::args().collect();
+        let Some((command, args)) = strings.split_first() else {
+            eprintln!("No program name, argv is empty");
+            std::process::exit(1)
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        Self::from_args_with_base(base, &[command], &args).unwrap_or_else(|early_exit| {
+            std::process::exit(match early_exit.status {
+                Ok(()) => {
+                    println!("{}", early_exit.output);
+                    0
+                }
+                Err(()) => {
+                    eprintln!(
+                        "{}\nRun {command} --help for more information.",
+                        early_exit.output
+                    );
+                    1
+                }
+            })
+        })
+    }
 }
 
 impl Default for RGBMatrixConfig {
@@ -121,6 +277,66 @@ impl Default for RGBMatrixConfig {
             row_setter: RowAddressSetterType::Direct,
             led_sequence: LedSequence::Rgb,
             led_brightness: 100,
+            color_correction: ColorCorrection::Cie1931,
+            white_balance: WhiteBalance::default(),
+            spatial_dithering: SpatialDithering::None,
+            update_thread_core: None,
+            pin_update_thread: true,
+            disable_rt_throttling: true,
+            performance_governor: true,
+            update_thread_scheduling: ThreadScheduling::MaxPriority,
+            watchdog_timeout_ms: None,
+            drop_privileges: None,
+            preview: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::option_given;
+    use crate::{HardwareMapping, NamedPixelMapperType, RGBMatrixConfig};
+
+    #[test]
+    fn test_option_given() {
+        let args = ["--rows", "32", "--chain-length=2", "--pixelmapper-extra"];
+        assert!(option_given(&args, "rows"));
+        assert!(option_given(&args, "chain_length"));
+        assert!(!option_given(&args, "pixelmapper"));
+        assert!(!option_given(&args, "cols"));
+    }
+
+    #[test]
+    fn test_merge_with_base() {
+        let base = RGBMatrixConfig {
+            rows: 32,
+            chain_length: 4,
+            pixelmapper: vec![NamedPixelMapperType::UMapper],
+            ..Default::default()
+        };
+        let config = RGBMatrixConfig::from_args_with_base(
+            base,
+            &["test"],
+            &[
+                "--chain-length",
+                "2",
+                "--pixelmapper",
+                "Rotate:90",
+                "--pixelmapper",
+                "Mirror:H",
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.rows, 32);
+        assert_eq!(config.chain_length, 2);
+        assert_eq!(
+            config.pixelmapper,
+            [
+                NamedPixelMapperType::Rotate(90),
+                NamedPixelMapperType::Mirror(true)
+            ]
+        );
+        // Not given on the command line, so the default of the base is kept instead of the flag default.
+        assert_eq!(config.hardware_mapping, HardwareMapping::adafruit_hat_pwm());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    str::FromStr,
+};
+
+use crate::{color::FRACTION_BITS, config::K_BIT_PLANES};
+
+/// How the luminance of a pixel is reduced to the bit planes that are shown. Compared to the temporal
+/// dithering of `dither_bits`, the spatial dithering spreads the error over neighboring pixels, which keeps
+/// gradients smooth with fewer PWM bits or at low brightness.
+///
+/// These options can be used with the `--spatial-dithering` flag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SpatialDithering {
+    /// The luminance is truncated.
+    #[default]
+    None,
+    /// Ordered dithering with a 4x4 Bayer matrix, for all pixels that are written.
+    Bayer,
+    /// Floyd-Steinberg error diffusion within the rectangle of a blit. Single pixels and fills use the
+    /// ordered dithering of [`SpatialDithering::Bayer`].
+    FloydSteinberg,
+}
+
+impl FromStr for SpatialDithering {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ok = match s {
+            "None" => Self::None,
+            "Bayer" => Self::Bayer,
+            "FloydSteinberg" => Self::FloydSteinberg,
+            other => return Err(format!("Invalid spatial dithering: {other}").into()),
+        };
+        Ok(ok)
+    }
+}
+
+impl Display for SpatialDithering {
+    /// The name as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+const BAYER_4X4: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The largest luminance value, with all bit planes and fraction bits set.
+const MAX_LUMINANCE: u32 = (1 << (K_BIT_PLANES + FRACTION_BITS)) - 1;
+
+/// The number of luminance bits that are not shown with `pwm_bits`, including the fraction bits.
+fn hidden_bits(pwm_bits: usize) -> usize {
+    FRACTION_BITS + K_BIT_PLANES - pwm_bits
+}
+
+/// Reduce the luminance of the pixel at `(x, y)` to the bit planes that are shown, with the lowest
+/// `K_BIT_PLANES - pwm_bits` planes cleared. Without dithering, the luminance is truncated.
+pub(crate) fn quantize_ordered(
+    dithering: bool,
+    x: usize,
+    y: usize,
+    luminance: [u16; 3],
+    pwm_bits: usize,
+) -> [u16; 3] {
+    let hidden_bits = hidden_bits(pwm_bits);
+    let threshold = if dithering {
+        // One of 16 evenly spaced thresholds within the hidden range.
+        ((2 * BAYER_4X4[y % 4][x % 4] + 1) << hidden_bits) >> 5
+    } else {
+        0
+    };
+    luminance.map(|value| {
+        let value = (u32::from(value) + threshold).min(MAX_LUMINANCE);
+        ((value >> hidden_bits) << (K_BIT_PLANES - pwm_bits)) as u16
+    })
+}
+
+/// The state of Floyd-Steinberg error diffusion over a rectangle that is written row by row.
+pub(crate) struct ErrorDiffusion {
+    pwm_bits: usize,
+    /// The error that is carried into the pixels of the current and of the next row.
+    current: Vec<[i32; 3]>,
+    next: Vec<[i32; 3]>,
+}
+
+impl ErrorDiffusion {
+    pub(crate) fn new(width: usize, pwm_bits: usize) -> Self {
+        Self {
+            pwm_bits,
+            current: vec![[0; 3]; width],
+            next: vec![[0; 3]; width],
+        }
+    }
+
+    /// Reduce the luminance of the pixel in `column` of the current row like [`quantize_ordered`] and pass
+    /// the error on to the pixels that come later.
+    pub(crate) fn quantize(&mut self, column: usize, luminance: [u16; 3]) -> [u16; 3] {
+        let hidden_bits = hidden_bits(self.pwm_bits);
+        let step = 1 << hidden_bits;
+        let max_level = ((1 << self.pwm_bits) - 1) << hidden_bits;
+        let width = self.current.len();
+        let mut quantized = [0; 3];
+        for channel in 0..3 {
+            let wanted = i32::from(luminance[channel]) + self.current[column][channel];
+            let level = ((wanted + step / 2) / step * step).clamp(0, max_level);
+            let error = wanted - level;
+            if column + 1 < width {
+                self.current[column + 1][channel] += error * 7 / 16;
+                self.next[column + 1][channel] += error / 16;
+            }
+            if column > 0 {
+                self.next[column - 1][channel] += error * 3 / 16;
+            }
+            self.next[column][channel] += error * 5 / 16;
+            quantized[channel] = (level >> FRACTION_BITS) as u16;
+        }
+        quantized
+    }
+
+    /// Continue with the next row.
+    pub(crate) fn next_row(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.next);
+        self.next.fill([0; 3]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quantize_ordered, ErrorDiffusion};
+
+    #[test]
+    fn test_dithering() {
+        // 2.5 times the lowest bit plane.
+        let luminance = [40, 0, 2047 << 4];
+        let ordered: Vec<[u16; 3]> = (0..16)
+            .map(|i| quantize_ordered(true, i % 4, i / 4, luminance, 11))
+            .collect();
+        assert_eq!(ordered.iter().map(|[r, _, _]| r).sum::<u16>(), 40);
+        assert!(ordered
+            .iter()
+            .all(|[r, g, b]| (2..=3).contains(r) && *g == 0 && *b == 2047));
+        assert_eq!(quantize_ordered(false, 3, 3, luminance, 11), [2, 0, 2047]);
+
+        // With 8 PWM bits, only multiples of 8 can be shown.
+        let coarse: Vec<[u16; 3]> = (0..16)
+            .map(|i| quantize_ordered(true, i % 4, i / 4, [20 << 4, 0, 0], 8))
+            .collect();
+        assert!(coarse.iter().all(|[r, _, _]| *r == 16 || *r == 24));
+        assert_eq!(coarse.iter().map(|[r, _, _]| r).sum::<u16>(), 20 * 16);
+
+        let mut diffusion = ErrorDiffusion::new(16, 8);
+        let mut total = 0;
+        for _ in 0..4 {
+            for column in 0..16 {
+                let [r, _, _] = diffusion.quantize(column, [20 << 4, 0, 0]);
+                assert!(r == 16 || r == 24);
+                total += u32::from(r);
+            }
+            diffusion.next_row();
+        }
+        assert!((total as i32 - 20 * 64).abs() <= 8);
+    }
+}
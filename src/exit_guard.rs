@@ -0,0 +1,267 @@
+use std::{
+    cell::Cell,
+    panic::{set_hook, take_hook},
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
+        Mutex, Once, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use libc::c_int;
+
+/// The signals that make the update threads blank the panels before the process exits.
+const EXIT_SIGNALS: [c_int; 4] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT];
+
+/// How long a panic waits for the update threads to blank the panels.
+const PANIC_BLANK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The number of update threads with an [`ExitGuard`] that blanks on signals. Read by the signal handler.
+static GUARDS: AtomicUsize = AtomicUsize::new(0);
+/// Set by the signal handler to stop the guarded update threads.
+static EXIT_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// The signal that requested the exit, re-raised once all guarded update threads stopped.
+static EXIT_SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// The number of update threads with an [`ExitGuard`] that blanks after panics. Read by the panic hook.
+static PANIC_GUARDS: AtomicUsize = AtomicUsize::new(0);
+/// Set by the panic hook to stop the update threads that blank after panics.
+static PANICKED: AtomicBool = AtomicBool::new(false);
+/// The signal actions that were replaced, restored once the last guard is dropped.
+static PREVIOUS_ACTIONS: Mutex<Vec<(c_int, libc::sigaction)>> = Mutex::new(Vec::new());
+static PANIC_HOOK: Once = Once::new();
+
+thread_local! {
+    static IS_GUARDED_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+extern "C" fn handle_exit_signal(signal: c_int) {
+    if GUARDS.load(Ordering::SeqCst) == 0 {
+        // The last guard was dropped while the signal was delivered. The previous actions are restored
+        // before that, so raising the signal again dispatches it to them once this handler returns.
+        unsafe {
+            libc::raise(signal);
+        }
+        return;
+    }
+    EXIT_SIGNAL.store(signal, Ordering::SeqCst);
+    EXIT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Stop the guarded update threads after a panic and wait until they blanked the panels, since the process
+/// may exit or abort right after the panic.
+fn blank_after_panic() {
+    if PANIC_GUARDS.load(Ordering::SeqCst) == 0 || IS_GUARDED_THREAD.with(Cell::get) {
+        return;
+    }
+    PANICKED.store(true, Ordering::SeqCst);
+    let start = Instant::now();
+    while PANIC_GUARDS.load(Ordering::SeqCst) > 0 && start.elapsed() < PANIC_BLANK_TIMEOUT {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Registers an update thread that blanks the panels when the process receives SIGINT, SIGTERM, SIGHUP or
+/// SIGQUIT, when any other thread panics, or both. The signal handlers are installed while at least one
+/// guard for signals exists and replace the handlers that were installed before, except for signals that
+/// are ignored. Once the last guarded update thread stopped, the previous handlers are restored and the
+/// signal is raised again, so the process exits like it would have without the guard.
+pub(crate) struct ExitGuard {
+    on_signal: bool,
+    on_panic: bool,
+}
+
+impl ExitGuard {
+    /// Register the current thread.
+    pub(crate) fn new(on_signal: bool, on_panic: bool) -> Self {
+        if on_panic {
+            PANIC_HOOK.call_once(|| {
+                let previous_hook = take_hook();
+                set_hook(Box::new(move |info| {
+                    previous_hook(info);
+                    blank_after_panic();
+                }));
+            });
+            PANIC_GUARDS.fetch_add(1, Ordering::SeqCst);
+        }
+        IS_GUARDED_THREAD.with(|guarded| guarded.set(true));
+        if on_signal {
+            Self::install_signal_handlers();
+        }
+        Self {
+            on_signal,
+            on_panic,
+        }
+    }
+
+    /// Install the signal handlers if this is the first guard for signals.
+    fn install_signal_handlers() {
+        let mut previous_actions = PREVIOUS_ACTIONS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if GUARDS.fetch_add(1, Ordering::SeqCst) == 0 {
+            for signal in EXIT_SIGNALS {
+                unsafe {
+                    let mut previous_action: libc::sigaction = std::mem::zeroed();
+                    // Ignored signals stay ignored, e.g. SIGHUP of a process started with `nohup`.
+                    if libc::sigaction(signal, std::ptr::null(), &mut previous_action) != 0
+                        || previous_action.sa_sigaction == libc::SIG_IGN
+                    {
+                        continue;
+                    }
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction =
+                        handle_exit_signal as extern "C" fn(c_int) as libc::sighandler_t;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    if libc::sigaction(signal, &action, std::ptr::null_mut()) == 0 {
+                        previous_actions.push((signal, previous_action));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove the signal handlers if this is the last guard for signals, and raise the signal that
+    /// requested the exit again.
+    fn remove_signal_handlers() {
+        let mut previous_actions = PREVIOUS_ACTIONS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Guards are only added and removed with the lock held. The previous actions are restored before
+        // the count drops to zero, so a signal that arrives in between still reaches a handler.
+        let last_guard = GUARDS.load(Ordering::SeqCst) == 1;
+        if last_guard {
+            for (signal, previous_action) in previous_actions.drain(..) {
+                unsafe {
+                    libc::sigaction(signal, &previous_action, std::ptr::null_mut());
+                }
+            }
+        }
+        GUARDS.fetch_sub(1, Ordering::SeqCst);
+        drop(previous_actions);
+        if !last_guard {
+            return;
+        }
+
+        EXIT_REQUESTED.store(false, Ordering::SeqCst);
+        let signal = EXIT_SIGNAL.swap(0, Ordering::SeqCst);
+        if signal != 0 {
+            unsafe {
+                libc::raise(signal);
+            }
+        }
+    }
+
+    /// Whether the update thread should blank the panels and stop.
+    pub(crate) fn exit_requested(&self) -> bool {
+        (self.on_signal && EXIT_REQUESTED.load(Ordering::SeqCst))
+            || (self.on_panic && PANICKED.load(Ordering::SeqCst))
+    }
+}
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        IS_GUARDED_THREAD.with(|guarded| guarded.set(false));
+        if self.on_panic && PANIC_GUARDS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            PANICKED.store(false, Ordering::SeqCst);
+        }
+        if self.on_signal {
+            Self::remove_signal_handlers();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process::Command,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        gpio_bits, simulated_backend::test_support::test_config, HardwareMapping, RGBMatrixBuilder,
+        RGBMatrixConfig, Reconfiguration, ReconfigurationError, SimulatedRegisterBackend,
+        ThreadState, UpdateError,
+    };
+
+    /// Set in the child process that runs a test on its own.
+    const CHILD_PROCESS_VARIABLE: &str = "RPI_LED_PANEL_TEST_CHILD";
+
+    /// Run the test `name` in a child process, where no other test runs in parallel. Returns whether the
+    /// caller is the child process and runs the test.
+    fn run_in_child_process(name: &str) -> bool {
+        if std::env::var_os(CHILD_PROCESS_VARIABLE).is_some() {
+            return true;
+        }
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", name, "--nocapture"])
+            .env(CHILD_PROCESS_VARIABLE, "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{stdout}{stderr}");
+        assert!(stdout.contains("1 passed"), "{stdout}{stderr}");
+        false
+    }
+
+    /// The signal handlers and the panic hook are process wide, so the test runs in a child process.
+    #[test]
+    fn test_blank_on_panic() {
+        if !run_in_child_process("exit_guard::tests::test_blank_on_panic") {
+            return;
+        }
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::adafruit_hat(),
+            ..test_config()
+        };
+        let output_enable = config.hardware_mapping.output_enable;
+        let disposition = |signal| unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signal, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        };
+        let sigterm_disposition = disposition(libc::SIGTERM);
+        let sighup_disposition = unsafe { libc::signal(libc::SIGHUP, libc::SIG_IGN) };
+
+        let backend = SimulatedRegisterBackend::new();
+        let probe = backend.clone();
+        let (mut matrix, mut canvas) = RGBMatrixBuilder::new(config)
+            .blank_on_exit(true)
+            .blank_on_panic(true)
+            .build_with_backend(move |_| Ok(backend))
+            .unwrap();
+        // Ignored signals are not handled.
+        assert_ne!(disposition(libc::SIGTERM), sigterm_disposition);
+        assert_eq!(disposition(libc::SIGHUP), libc::SIG_IGN);
+        canvas.fill(255, 255, 255);
+        canvas = matrix.update_on_vsync(canvas).unwrap();
+
+        // A panic in another thread stops the update thread once the panels are blank.
+        assert!(std::thread::spawn(|| panic!("Panic in another thread."))
+            .join()
+            .is_err());
+        let start = Instant::now();
+        while matrix.thread_state() == ThreadState::Running {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            matrix.thread_state(),
+            ThreadState::Stopped(UpdateError::ThreadExited)
+        );
+        assert_eq!(
+            matrix.update_on_vsync(canvas).err(),
+            Some(UpdateError::ThreadExited)
+        );
+        assert!(matches!(
+            matrix.reconfigure(Reconfiguration::Brightness(50)),
+            Err(ReconfigurationError::Stopped(UpdateError::ThreadExited))
+        ));
+        assert_eq!(probe.output_levels() & output_enable, output_enable);
+        assert_eq!(probe.output_levels() & gpio_bits!(5, 13, 6, 12, 16, 23), 0);
+
+        // The handlers are removed with the last update thread.
+        assert_eq!(disposition(libc::SIGTERM), sigterm_disposition);
+        unsafe { libc::signal(libc::SIGHUP, sighup_disposition) };
+    }
+}
@@ -7,8 +7,10 @@ use crate::{
     chip::PiChip,
     config::K_BIT_PLANES,
     gpio_bits,
+    logging::log_debug,
     pin_pulser::{HardwarePinPulser, PinPulser, TimerBasedPinPulser},
-    registers::{ClkRegisters, GPIOFunction, GPIORegisters, PWMRegisters, TimeRegisters},
+    register_backend::RegisterBackend,
+    registers::GPIOFunction,
     row_address_setter::RowAddressSetter,
     utils::linux_has_module_loaded,
     RGBMatrixConfig,
@@ -41,34 +43,60 @@ impl Display for GpioInitializationError {
     }
 }
 
-pub(crate) struct Gpio {
-    gpio_registers: GPIORegisters,
-    time_registers: TimeRegisters,
-    pwm_registers: PWMRegisters,
-    pin_pulser: Box<dyn PinPulser>,
+/// The durations of the bit planes. With dithering, the lowest bit planes have the same duration.
+fn bitplane_timings(pwm_lsb_nanoseconds: u32, dither_bits: usize) -> Vec<u32> {
+    let mut bitplane_timings = Vec::new();
+    let mut timing_ns = pwm_lsb_nanoseconds;
+    (0..K_BIT_PLANES).for_each(|b| {
+        bitplane_timings.push(timing_ns);
+        if b >= dither_bits {
+            timing_ns *= 2;
+        };
+    });
+    bitplane_timings
+}
+
+fn create_pin_pulser<B: RegisterBackend>(
+    hardware_pulse: bool,
+    output_enable: u32,
+    bitplane_timings: &[u32],
+    backend: &mut B,
+) -> Box<dyn PinPulser<B>> {
+    if hardware_pulse {
+        Box::new(HardwarePinPulser::new(
+            output_enable,
+            bitplane_timings,
+            backend,
+        ))
+    } else {
+        Box::new(TimerBasedPinPulser::new(bitplane_timings, output_enable))
+    }
+}
+
+pub(crate) struct Gpio<B: RegisterBackend> {
+    backend: B,
+    pin_pulser: Box<dyn PinPulser<B>>,
+    /// Whether the output enable is pulsed by the PWM block.
+    hardware_pulse: bool,
+    output_enable: u32,
     input_bits: u32,
     output_bits: u32,
     reserved_bits: u32,
     gpio_slowdown: u32,
 }
 
-impl Gpio {
-    /// Initialize GPIO and loads all registers. Needs root privileges.
+impl<B: RegisterBackend> Gpio<B> {
+    /// Initialize GPIO on top of a register backend.
     pub(crate) fn new(
         chip: PiChip,
         config: &RGBMatrixConfig,
-        address_setter: &dyn RowAddressSetter,
+        address_setter: &dyn RowAddressSetter<B>,
+        mut backend: B,
     ) -> Result<Self, GpioInitializationError> {
         if linux_has_module_loaded("snd_bcm2835") {
             return Err(GpioInitializationError::SoundModuleLoaded);
         }
 
-        let mut gpio_registers = GPIORegisters::new(chip);
-        let time_registers = TimeRegisters::new(chip);
-        let mut pwm_registers = PWMRegisters::new(chip);
-        let mut clk_registers = ClkRegisters::new(chip);
-        // TODO: We can drop privileges here.
-
         // Tell GPIO about all bits we intend to use.
         let mut all_used_bits: u32 = 0;
         all_used_bits |= config.hardware_mapping.used_bits();
@@ -90,8 +118,8 @@ impl Gpio {
             // can switch between the two modes "adafruit-hat" and "adafruit-hat-pwm"
             // without trouble.
             {
-                gpio_registers.select_function(4, GPIOFunction::Input);
-                gpio_registers.select_function(18, GPIOFunction::Input);
+                backend.select_function(4, GPIOFunction::Input);
+                backend.select_function(18, GPIOFunction::Input);
                 // Even with PWM enabled, GPIO4 still can not be used, because it is
                 // now connected to the GPIO18 and thus must stay an input.
                 // So reserve this bit if it is not set in outputs.
@@ -107,43 +135,42 @@ impl Gpio {
             let k_max_available_bit = 31;
             (0..=k_max_available_bit).for_each(|b| {
                 if output_bits & gpio_bits!(b) != 0 {
-                    gpio_registers.select_function(b, GPIOFunction::Output);
+                    backend.select_function(b, GPIOFunction::Output);
                 }
             });
         }
         assert!(output_bits == all_used_bits);
 
-        let mut bitplane_timings = Vec::new();
-        let mut timing_ns = config.pwm_lsb_nanoseconds;
-        (0..K_BIT_PLANES).for_each(|b| {
-            bitplane_timings.push(timing_ns);
-            if b >= config.dither_bits {
-                timing_ns *= 2;
-            };
-        });
+        // The output enable is active low. Keep the panels dark until the first pulse is sent.
+        backend.write_set_bits(config.hardware_mapping.output_enable);
 
-        let pin_pulser = if config.hardware_mapping.output_enable == gpio_bits!(18) || config.hardware_mapping.output_enable == gpio_bits!(12) {
-            Box::new(HardwarePinPulser::new(
-                config.hardware_mapping.output_enable,
-                &bitplane_timings,
-                &mut pwm_registers,
-                &mut gpio_registers,
-                &mut clk_registers,
-            )) as Box<dyn PinPulser>
-        } else {
-            Box::new(TimerBasedPinPulser::new(
-                &bitplane_timings,
-                config.hardware_mapping.output_enable,
-            ))
-        };
+        let hardware_pulse =
+            backend.supports_hardware_pwm() && config.hardware_mapping.has_pwm_output_enable();
+        let output_enable = config.hardware_mapping.output_enable;
+        let pin_pulser = create_pin_pulser(
+            hardware_pulse,
+            output_enable,
+            &bitplane_timings(config.pwm_lsb_nanoseconds, config.dither_bits),
+            &mut backend,
+        );
 
         let gpio_slowdown = config.slowdown.unwrap_or_else(|| chip.gpio_slowdown());
 
+        log_debug!(
+            pulser = if hardware_pulse { "hardware" } else { "timer" };
+            "Pulsing the output enable with the {} pin pulser",
+            if hardware_pulse { "hardware PWM" } else { "timer based" }
+        );
+        log_debug!(
+            slowdown = gpio_slowdown, automatic = config.slowdown.is_none();
+            "GPIO slowdown {gpio_slowdown}"
+        );
+
         Ok(Self {
-            gpio_registers,
-            time_registers,
-            pwm_registers,
+            backend,
             pin_pulser,
+            hardware_pulse,
+            output_enable,
             input_bits,
             output_bits,
             reserved_bits,
@@ -161,7 +188,7 @@ impl Gpio {
             return;
         };
         for _ in 0..=self.gpio_slowdown {
-            self.gpio_registers.write_clr_bits(value);
+            self.backend.write_clr_bits(value);
         }
     }
 
@@ -170,29 +197,37 @@ impl Gpio {
             return;
         };
         for _ in 0..=self.gpio_slowdown {
-            self.gpio_registers.write_set_bits(value);
+            self.backend.write_set_bits(value);
         }
     }
 
+    /// Recompute the pulse durations of the bit planes, after the current pulse has finished.
+    pub(crate) fn set_bitplane_timings(&mut self, pwm_lsb_nanoseconds: u32, dither_bits: usize) {
+        self.wait_pulse_finished();
+        self.pin_pulser = create_pin_pulser(
+            self.hardware_pulse,
+            self.output_enable,
+            &bitplane_timings(pwm_lsb_nanoseconds, dither_bits),
+            &mut self.backend,
+        );
+    }
+
     pub(crate) fn send_pulse(&mut self, bitplane: usize) {
         let Gpio {
-            time_registers,
-            pwm_registers,
+            backend,
             pin_pulser,
-            gpio_registers,
             ..
         } = self;
-        pin_pulser.send_pulse(bitplane, gpio_registers, pwm_registers, time_registers);
+        pin_pulser.send_pulse(bitplane, backend);
     }
 
     pub(crate) fn wait_pulse_finished(&mut self) {
         let Gpio {
-            time_registers,
-            pwm_registers,
+            backend,
             pin_pulser,
             ..
         } = self;
-        pin_pulser.wait_pulse_finished(time_registers, pwm_registers);
+        pin_pulser.wait_pulse_finished(backend);
     }
 
     pub(crate) fn request_enabled_inputs(&mut self, mut enabled_bits: u32) -> u32 {
@@ -202,7 +237,7 @@ impl Gpio {
         let k_max_available_bit = 31;
         (0..=k_max_available_bit).for_each(|b| {
             if (enabled_bits & gpio_bits!(b)) != 0 {
-                self.gpio_registers.select_function(b, GPIOFunction::Input);
+                self.backend.select_function(b, GPIOFunction::Input);
             }
         });
         self.input_bits |= enabled_bits;
@@ -210,16 +245,16 @@ impl Gpio {
     }
 
     pub(crate) fn read(&mut self) -> u32 {
-        self.gpio_registers.read_pin_level0() & self.input_bits
+        self.backend.read_pin_level0() & self.input_bits
     }
 
     /// Time instant in microseconds.
     pub(crate) fn get_time(&self) -> u64 {
-        self.time_registers.get_time()
+        self.backend.get_time()
     }
 
     /// Sleep for exactly this many microseconds.
     pub(crate) fn sleep(&mut self, duration_us: u64) {
-        self.time_registers.sleep(duration_us);
+        self.backend.sleep(duration_us);
     }
 }
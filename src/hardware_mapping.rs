@@ -1,4 +1,9 @@
-use std::{error::Error, ops::BitOr, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    ops::BitOr,
+    str::FromStr,
+};
 
 use crate::gpio_bits;
 
@@ -88,10 +93,21 @@ pub struct HardwareMapping {
     pub(crate) panels: Panels,
 }
 
+/// The highest GPIO pin on the 40 pin header.
+const MAX_HEADER_GPIO: u32 = 27;
+
+/// Names of the color pins of a chain in a pin specification.
+const COLOR_PIN_NAMES: [&str; 6] = ["r1", "g1", "b1", "r2", "g2", "b2"];
+
 impl FromStr for HardwareMapping {
     type Err = Box<dyn Error>;
 
+    /// Either the name of a built-in mapping or a pin specification, see
+    /// [`HardwareMapping::from_pin_spec`].
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('=') {
+            return Self::from_pin_spec(s);
+        }
         match s {
             "AdafruitHat" => Ok(Self::adafruit_hat()),
             "AdafruitHatPwm" => Ok(Self::adafruit_hat_pwm()),
@@ -104,8 +120,191 @@ impl FromStr for HardwareMapping {
     }
 }
 
+impl Display for HardwareMapping {
+    /// The name of a built-in mapping or the pin specification of a custom one, as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let named = [
+            ("AdafruitHat", Self::adafruit_hat()),
+            ("AdafruitHatPwm", Self::adafruit_hat_pwm()),
+            ("Regular", Self::regular()),
+            ("RegularPi1", Self::regular_pi1()),
+            ("Classic", Self::classic()),
+            ("ClassicPi1", Self::classic_pi1()),
+        ];
+        match named.iter().find(|(_, mapping)| mapping == self) {
+            Some((name, _)) => f.write_str(name),
+            None => f.write_str(&self.pin_spec()),
+        }
+    }
+}
+
+impl ColorBits {
+    fn pins_mut(&mut self) -> [&mut u32; 6] {
+        [
+            &mut self.r1,
+            &mut self.g1,
+            &mut self.b1,
+            &mut self.r2,
+            &mut self.g2,
+            &mut self.b2,
+        ]
+    }
+
+    fn pins(&self) -> [u32; 6] {
+        [self.r1, self.g1, self.b1, self.r2, self.g2, self.b2]
+    }
+}
+
 impl HardwareMapping {
-    pub(crate) fn used_bits(&self) -> u32 {
+    /// Create a mapping from a pin specification: comma separated `signal=gpio` pairs, e.g.
+    /// `oe=18,clk=17,lat=4,a=22,b=23,c=24,p0.r1=11,p0.g1=27,p0.b1=7,p0.r2=8,p0.g2=9,p0.b2=10`.
+    ///
+    /// The signals are `oe`, `clk`, `lat`, the row address lines `a` to `e` and the color pins `r1`, `g1`,
+    /// `b1`, `r2`, `g2` and `b2` of the parallel chains `p0` to `p5`. A signal can be connected to multiple
+    /// pins by joining them with a `+`. `oe`, `clk`, `lat`, `a` and the pins of chain `p0` are required.
+    /// The chains have to be numbered consecutively and have all six color pins, and no pin can be used
+    /// twice.
+    pub fn from_pin_spec(spec: &str) -> Result<Self, Box<dyn Error>> {
+        let mut mapping = Self {
+            output_enable: 0,
+            clock: 0,
+            strobe: 0,
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            panels: Panels {
+                color_bits: [ColorBits::unused(); 6],
+            },
+        };
+        let mut pin_users: [Option<&str>; MAX_HEADER_GPIO as usize + 1] = Default::default();
+        for entry in spec.split(',').map(str::trim) {
+            let (signal, pins) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("'{entry}' is not of the form 'signal=gpio'."))?;
+            let signal = signal.trim();
+            let bits = mapping
+                .signal_mut(signal)
+                .ok_or_else(|| format!("'{signal}' is not a valid signal."))?;
+            if *bits != 0 {
+                return Err(format!("'{signal}' is assigned twice.").into());
+            }
+            for pin in pins.split('+').map(str::trim) {
+                let pin: u32 = pin
+                    .parse()
+                    .map_err(|_| format!("'{pin}' is not a valid GPIO pin of '{signal}'."))?;
+                if pin > MAX_HEADER_GPIO {
+                    return Err(format!(
+                        "GPIO {pin} of '{signal}' is out of range, the header has GPIO 0 to \
+                        {MAX_HEADER_GPIO}."
+                    )
+                    .into());
+                }
+                if let Some(user) = pin_users[pin as usize] {
+                    return Err(
+                        format!("GPIO {pin} is used by both '{user}' and '{signal}'.").into(),
+                    );
+                }
+                pin_users[pin as usize] = Some(signal);
+                *bits |= gpio_bits!(pin);
+            }
+        }
+        for (signal, bits) in [
+            ("oe", mapping.output_enable),
+            ("clk", mapping.clock),
+            ("lat", mapping.strobe),
+            ("a", mapping.a),
+        ] {
+            if bits == 0 {
+                return Err(format!("The required signal '{signal}' is missing.").into());
+            }
+        }
+        let mut chain_ended = false;
+        for (chain, color_bits) in mapping.panels.color_bits.iter().enumerate() {
+            let assigned = color_bits.pins().iter().filter(|bits| **bits != 0).count();
+            match assigned {
+                0 if chain == 0 => return Err("The pins of chain 'p0' are missing.".into()),
+                0 => chain_ended = true,
+                6 if chain_ended => {
+                    return Err(format!(
+                        "Chain 'p{chain}' is used, but chain 'p{}' is missing.",
+                        chain - 1
+                    )
+                    .into())
+                }
+                6 => {}
+                _ => {
+                    let missing: Vec<String> = COLOR_PIN_NAMES
+                        .iter()
+                        .zip(color_bits.pins())
+                        .filter(|(_, bits)| *bits == 0)
+                        .map(|(name, _)| format!("'p{chain}.{name}'"))
+                        .collect();
+                    return Err(
+                        format!("Chain 'p{chain}' is missing {}.", missing.join(", ")).into(),
+                    );
+                }
+            }
+        }
+        Ok(mapping)
+    }
+
+    fn signal_mut(&mut self, signal: &str) -> Option<&mut u32> {
+        match signal {
+            "oe" => Some(&mut self.output_enable),
+            "clk" => Some(&mut self.clock),
+            "lat" => Some(&mut self.strobe),
+            "a" => Some(&mut self.a),
+            "b" => Some(&mut self.b),
+            "c" => Some(&mut self.c),
+            "d" => Some(&mut self.d),
+            "e" => Some(&mut self.e),
+            _ => {
+                let (chain, color) = signal.strip_prefix('p')?.split_once('.')?;
+                let chain: usize = chain.parse().ok()?;
+                let index = COLOR_PIN_NAMES.iter().position(|name| *name == color)?;
+                let color_bits = self.panels.color_bits.get_mut(chain)?;
+                color_bits.pins_mut().into_iter().nth(index)
+            }
+        }
+    }
+
+    /// The pin specification of this mapping, see [`HardwareMapping::from_pin_spec`].
+    #[must_use]
+    pub fn pin_spec(&self) -> String {
+        let mut signals = vec![
+            ("oe".to_string(), self.output_enable),
+            ("clk".to_string(), self.clock),
+            ("lat".to_string(), self.strobe),
+            ("a".to_string(), self.a),
+            ("b".to_string(), self.b),
+            ("c".to_string(), self.c),
+            ("d".to_string(), self.d),
+            ("e".to_string(), self.e),
+        ];
+        for (chain, color_bits) in self.panels.color_bits.iter().enumerate() {
+            for (name, bits) in COLOR_PIN_NAMES.iter().zip(color_bits.pins()) {
+                signals.push((format!("p{chain}.{name}"), bits));
+            }
+        }
+        signals
+            .into_iter()
+            .filter(|(_, bits)| *bits != 0)
+            .map(|(signal, bits)| {
+                let pins: Vec<String> = (0..u32::BITS)
+                    .filter(|pin| bits & gpio_bits!(pin) != 0)
+                    .map(|pin| pin.to_string())
+                    .collect();
+                format!("{signal}={}", pins.join("+"))
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// All GPIO pins that the mapping uses.
+    #[must_use]
+    pub fn used_bits(&self) -> u32 {
         self.output_enable | self.clock | self.strobe | self.panels.used_bits()
     }
 
@@ -121,7 +320,15 @@ impl HardwareMapping {
         color_clk_mask
     }
 
-    pub(crate) fn max_parallel_chains(&self) -> usize {
+    /// Whether the output enable is on a pin that the PWM block can drive.
+    #[must_use]
+    pub fn has_pwm_output_enable(&self) -> bool {
+        self.output_enable == gpio_bits!(18) || self.output_enable == gpio_bits!(12)
+    }
+
+    /// The number of parallel chains that the mapping has color pins for.
+    #[must_use]
+    pub fn max_parallel_chains(&self) -> usize {
         self.panels
             .color_bits
             .iter()
@@ -350,3 +557,47 @@ impl HardwareMapping {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::HardwareMapping;
+
+    #[test]
+    fn test_pin_spec() {
+        let spec = "oe=18,clk=17,lat=4,a=22,b=23,c=24,d=25,e=15,\
+            p0.r1=11,p0.g1=27,p0.b1=7,p0.r2=8,p0.g2=9,p0.b2=10,\
+            p1.r1=12,p1.g1=5,p1.b1=6,p1.r2=19,p1.g2=13,p1.b2=20,\
+            p2.r1=14,p2.g1=2,p2.b1=3,p2.r2=26,p2.g2=16,p2.b2=21";
+        let mapping: HardwareMapping = spec.parse().unwrap();
+        assert_eq!(mapping, HardwareMapping::regular());
+        assert_eq!(mapping.max_parallel_chains(), 3);
+        assert!(mapping.has_pwm_output_enable());
+
+        let spec =
+            "oe=4,clk=17,lat=21,a=22,b=26,p0.r1=5,p0.g1=13,p0.b1=6,p0.r2=12,p0.g2=16,p0.b2=23+24";
+        let mapping: HardwareMapping = spec.parse().unwrap();
+        assert_eq!(mapping.max_parallel_chains(), 1);
+        assert!(!mapping.has_pwm_output_enable());
+        assert_eq!(mapping.to_string(), spec);
+        assert_eq!(
+            mapping.to_string().parse::<HardwareMapping>().unwrap(),
+            mapping
+        );
+    }
+
+    #[test]
+    fn test_pin_spec_errors() {
+        let chain = "p0.r1=5,p0.g1=13,p0.b1=6,p0.r2=12,p0.g2=16,p0.b2=23";
+        let error = |spec: &str| spec.parse::<HardwareMapping>().unwrap_err().to_string();
+        assert!(error(&format!("oe=4,clk=17,lat=21,a=5,{chain}")).contains("used by both"));
+        assert!(error(&format!("oe=4,clk=17,lat=28,a=22,{chain}")).contains("out of range"));
+        assert!(error(&format!("oe=4,oe=18,clk=17,lat=21,a=22,{chain}")).contains("twice"));
+        assert!(error(&format!("clk=17,lat=21,a=22,{chain}")).contains("'oe' is missing"));
+        assert!(error(&format!("oe=4,clk=17,lat=21,a=22,{chain},p1.r1=2")).contains("'p1.g1'"));
+        assert!(error(&format!("oe=4,clk=17,lat=21,a=22,x=3,{chain}")).contains("'x'"));
+        let gap = "p2.r1=7,p2.g1=8,p2.b1=9,p2.r2=10,p2.g2=11,p2.b2=14";
+        assert!(
+            error(&format!("oe=4,clk=17,lat=21,a=22,{chain},{gap}")).contains("'p1' is missing")
+        );
+    }
+}
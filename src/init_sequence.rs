@@ -1,6 +1,10 @@
-use std::{error::Error, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    str::FromStr,
+};
 
-use crate::{gpio::Gpio, gpio_bits, RGBMatrixConfig};
+use crate::{gpio::Gpio, gpio_bits, register_backend::RegisterBackend, RGBMatrixConfig};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PanelType {
@@ -20,15 +24,26 @@ impl FromStr for PanelType {
     }
 }
 
+impl Display for PanelType {
+    /// The name as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 impl PanelType {
-    pub(crate) fn run_init_sequence(self, gpio: &mut Gpio, config: &RGBMatrixConfig) {
+    pub(crate) fn run_init_sequence<B: RegisterBackend>(
+        self,
+        gpio: &mut Gpio<B>,
+        config: &RGBMatrixConfig,
+    ) {
         match self {
             Self::FM6126 => Self::init_fm6126(gpio, config),
             Self::FM6127 => Self::init_fm6127(gpio, config),
         }
     }
 
-    fn init_fm6126(gpio: &mut Gpio, config: &RGBMatrixConfig) {
+    fn init_fm6126<B: RegisterBackend>(gpio: &mut Gpio<B>, config: &RGBMatrixConfig) {
         let hm = &config.hardware_mapping;
         let columns = config.cols;
         let bits_on = hm.panels.used_bits() | hm.a;
@@ -73,7 +88,7 @@ impl PanelType {
 
     /// The FM6217 is very similar to the FM6216. FM6217 adds Register 3 to allow for automatic bad pixel
     /// suppression.
-    fn init_fm6127(gpio: &mut Gpio, config: &RGBMatrixConfig) {
+    fn init_fm6127<B: RegisterBackend>(gpio: &mut Gpio<B>, config: &RGBMatrixConfig) {
         let hm = &config.hardware_mapping;
         let columns = config.cols;
         let bits_on = hm.panels.color_bits[0].used_bits() | hm.a;
@@ -1,25 +1,59 @@
+mod async_update;
 mod canvas;
 mod chip;
 mod color;
 mod config;
+mod dithering;
+mod exit_guard;
 mod gpio;
 mod hardware_mapping;
 mod init_sequence;
+mod logging;
 mod multiplex_mapper;
 mod named_pixel_mapper;
+mod panel_emulator;
 mod pin_pulser;
 mod pixel_mapper;
+mod privileges;
+mod register_backend;
 mod registers;
 mod rgb_matrix;
 mod row_address_setter;
+mod rp1_registers;
+#[cfg(feature = "serde")]
+mod serialization;
+mod terminal_preview;
+mod simulated_backend;
+mod system_tuning;
 mod utils;
+mod waveform;
 
+pub use async_update::{InputStream, NextInput, UpdateFuture};
 pub use canvas::{Canvas, LedSequence};
 pub use chip::PiChip;
+pub use color::{ColorCorrection, WhiteBalance};
 pub use config::RGBMatrixConfig;
+pub use dithering::SpatialDithering;
 pub use hardware_mapping::HardwareMapping;
 pub use init_sequence::PanelType;
 pub use multiplex_mapper::MultiplexMapperType;
-pub use rgb_matrix::RGBMatrix;
+pub use rgb_matrix::{RGBMatrix, RGBMatrixBuilder};
 pub use row_address_setter::RowAddressSetterType;
+pub use rp1_registers::Rp1RegisterBackend;
 pub use named_pixel_mapper::NamedPixelMapperType;
+pub use panel_emulator::{EmulatedFrame, PanelEmulator};
+pub use pixel_mapper::CustomPixelMapper;
+pub use privileges::{PrivilegeDrop, PrivilegeDropError};
+pub use register_backend::{MmapRegisterBackend, RegisterBackend};
+pub use registers::GPIOFunction;
+pub use rgb_matrix::{
+    MatrixCreationError, Reconfiguration, ReconfigurationError, ThreadState, TryUpdateError,
+    UpdateError,
+};
+pub use simulated_backend::SimulatedRegisterBackend;
+pub use system_tuning::ThreadScheduling;
+pub use terminal_preview::render_ansi;
+pub use waveform::{
+    Hub75Event, Hub75Signal, Hub75Trace, Latch, RecordedEvent, RecordingRegisterBackend,
+    RegisterEvent, SignalTransition, WaveformRecording,
+};
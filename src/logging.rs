@@ -0,0 +1,114 @@
+//! Diagnostics of the library. With the `log` feature, they are emitted through the [`log`] facade,
+//! including key-value pairs. Without it, warnings are printed to stderr and the other levels are dropped.
+
+/// Something that affects the display quality, like a system setting that could not be applied.
+macro_rules! log_warn {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::warn!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        eprintln!($($arg)+);
+    };
+}
+
+/// A notable event, like the start of the matrix. Accepts the key-value syntax of [`log`].
+macro_rules! log_info {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::info!($($arg)+);
+    };
+}
+
+/// Details of the setup, like the chosen pin pulser. Accepts the key-value syntax of [`log`].
+macro_rules! log_debug {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        ::log::debug!($($arg)+);
+    };
+}
+
+pub(crate) use {log_debug, log_info, log_warn};
+
+#[cfg(all(test, feature = "log"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use log::{
+        kv::{Error, Key, Value, VisitSource},
+        Level, LevelFilter, Log, Metadata, Record,
+    };
+
+    use crate::{
+        simulated_backend::test_support::{simulated_matrix, test_config},
+        RGBMatrixConfig,
+    };
+
+    /// A logged message with its key-value pairs.
+    type LoggedRecord = (Level, String, Vec<(String, String)>);
+
+    static RECORDS: Mutex<Vec<LoggedRecord>> = Mutex::new(Vec::new());
+
+    struct TestLogger;
+
+    impl Log for TestLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            struct Collect(Vec<(String, String)>);
+            impl<'kvs> VisitSource<'kvs> for Collect {
+                fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+                    self.0.push((key.to_string(), value.to_string()));
+                    Ok(())
+                }
+            }
+            let mut pairs = Collect(Vec::new());
+            record.key_values().visit(&mut pairs).unwrap();
+            RECORDS
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string(), pairs.0));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_structured_events() {
+        log::set_logger(&TestLogger).unwrap();
+        log::set_max_level(LevelFilter::Debug);
+
+        let (_matrix, _canvas) = simulated_matrix(RGBMatrixConfig {
+            slowdown: Some(2),
+            ..test_config()
+        });
+
+        // Other tests may log concurrently, so look for a record with all the expected pairs.
+        let records = RECORDS.lock().unwrap();
+        let logged = |level: Level, message: &str, expected: &[(&str, &str)]| {
+            records.iter().any(|(record_level, args, pairs)| {
+                *record_level == level
+                    && args.starts_with(message)
+                    && expected
+                        .iter()
+                        .all(|(key, value)| pairs.contains(&(key.to_string(), value.to_string())))
+            })
+        };
+        assert!(logged(
+            Level::Info,
+            "Matrix started",
+            &[("chip", "BCM2708"), ("rows", "16"), ("cols", "32")]
+        ));
+        assert!(logged(
+            Level::Debug,
+            "GPIO slowdown",
+            &[("slowdown", "2"), ("automatic", "false")]
+        ));
+        assert!(logged(
+            Level::Debug,
+            "Pulsing the output enable",
+            &[("pulser", "hardware")]
+        ));
+    }
+}
@@ -1,4 +1,8 @@
-use std::{error::Error, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    str::FromStr,
+};
 
 use crate::rgb_matrix::MatrixCreationError;
 
@@ -52,6 +56,13 @@ impl FromStr for MultiplexMapperType {
     }
 }
 
+impl Display for MultiplexMapperType {
+    /// The name as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 impl MultiplexMapperType {
     pub(crate) fn create(self) -> Box<dyn MultiplexMapper> {
         match self {
@@ -1,4 +1,10 @@
-use std::{error::Error, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    fs::read_to_string,
+    path::PathBuf,
+    str::FromStr,
+};
 
 use crate::rgb_matrix::MatrixCreationError;
 
@@ -10,7 +16,7 @@ use crate::rgb_matrix::MatrixCreationError;
 /// You can apply multiple mappers in your configuration, and they will be applied in the order you specify.
 /// For example, to first mirror the panels horizontally and then rotate the resulting screen,
 /// You can use `--pixelmapper Mirror:H --pixelmapper Rotate:90`
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum NamedPixelMapperType {
     /// The "Mirror" mapper allows you to mirror the output either horizontally or vertically.
     /// Specify 'H' for horizontal mirroring or 'V' for vertical mirroring as a parameter after a colon.
@@ -45,6 +51,28 @@ pub enum NamedPixelMapperType {
     ///   [<][<][<][<]  }--- Pi connector #2
     ///   [>][>][>][>]
     UMapper,
+    /// The "Layout" mapper places every panel at an arbitrary position on the visible canvas, as described
+    /// by a layout file. Specify the path of the file as a parameter after a colon.
+    /// Example: `--pixelmapper Layout:/etc/led-wall.layout`
+    ///
+    /// Each line of the file places one panel and has the form `column row x y [rotation] [H|V]`:
+    /// * `column` and `row` select the panel in the unmapped matrix. The column counts from the left,
+    ///   i.e. from the panel at the end of the chain, and the row is the parallel chain.
+    /// * `x` and `y` are the position of the top left corner of the panel on the visible canvas.
+    /// * `rotation` rotates the panel by a multiple of 90 degrees like the "Rotate" mapper does.
+    /// * `H` or `V` mirrors the panel like the "Mirror" mapper does, before it is rotated.
+    ///
+    /// Empty lines and lines starting with `#` are ignored. Every panel has to be placed exactly once and
+    /// the panels have to cover the visible canvas without overlapping.
+    ///
+    /// For example, a chain of two 32x32 panels on one chain can be stacked, with the lower panel
+    /// upside down:
+    /// ```text
+    /// # column row x y rotation
+    /// 0 0 0 0
+    /// 1 0 0 32 180
+    /// ```
+    Layout(PathBuf),
 }
 
 impl FromStr for NamedPixelMapperType {
@@ -73,6 +101,12 @@ impl FromStr for NamedPixelMapperType {
                     }
                     Err("Rotation angle is missing or invalid".into())
                 }
+                "Layout" => {
+                    if param.is_empty() {
+                        return Err("Layout file path is missing".into());
+                    }
+                    Ok(Self::Layout(PathBuf::from(param)))
+                }
                 other => Err(format!("'{other}' is not a valid Pixel mapping.").into()),
             }
         } else if s == "U-mapper" {
@@ -83,20 +117,46 @@ impl FromStr for NamedPixelMapperType {
     }
 }
 
+impl Display for NamedPixelMapperType {
+    /// The mapping as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NamedPixelMapperType::Mirror(true) => f.write_str("Mirror:H"),
+            NamedPixelMapperType::Mirror(false) => f.write_str("Mirror:V"),
+            NamedPixelMapperType::Rotate(angle) => write!(f, "Rotate:{angle}"),
+            NamedPixelMapperType::UMapper => f.write_str("U-mapper"),
+            NamedPixelMapperType::Layout(path) => write!(f, "Layout:{}", path.display()),
+        }
+    }
+}
+
 impl NamedPixelMapperType {
     pub(crate) fn create(
-        self,
+        &self,
         chain: usize,
         parallel: usize,
     ) -> Result<Box<dyn NamedPixelMapper>, MatrixCreationError> {
         match self {
-            NamedPixelMapperType::Mirror(horizontal) => {
-                Ok(Box::new(MirrorPixelMapper { horizontal }))
+            NamedPixelMapperType::Mirror(horizontal) => Ok(Box::new(MirrorPixelMapper {
+                horizontal: *horizontal,
+            })),
+            NamedPixelMapperType::Rotate(angle) => {
+                Ok(Box::new(RotatePixelMapper { angle: *angle }))
             }
-            NamedPixelMapperType::Rotate(angle) => Ok(Box::new(RotatePixelMapper { angle })),
             NamedPixelMapperType::UMapper => Ok(Box::new(UArrangeMapper::new_with_parameters(
                 chain, parallel,
             )?)),
+            NamedPixelMapperType::Layout(path) => {
+                let layout = read_to_string(path).map_err(|error| {
+                    MatrixCreationError::PixelMapperError(format!(
+                        "LayoutMapper: Could not read '{}': {error}",
+                        path.display()
+                    ))
+                })?;
+                Ok(Box::new(LayoutPixelMapper::parse(
+                    &layout, chain, parallel,
+                )?))
+            }
         }
     }
 }
@@ -246,3 +306,248 @@ impl NamedPixelMapper for UArrangeMapper {
         [matrix_x, base_y + matrix_y]
     }
 }
+
+/// A panel of the [`LayoutPixelMapper`].
+struct PanelPlacement {
+    column: usize,
+    row: usize,
+    x: usize,
+    y: usize,
+    mirror: Option<MirrorPixelMapper>,
+    rotate: RotatePixelMapper,
+}
+
+impl PanelPlacement {
+    /// The size of the panel on the visible canvas.
+    fn visible_size(&self, panel_width: usize, panel_height: usize) -> [usize; 2] {
+        if self.rotate.angle % 180 == 0 {
+            [panel_width, panel_height]
+        } else {
+            [panel_height, panel_width]
+        }
+    }
+
+    fn contains(&self, panel_size: [usize; 2], x: usize, y: usize) -> bool {
+        let [width, height] = self.visible_size(panel_size[0], panel_size[1]);
+        (self.x..self.x + width).contains(&x) && (self.y..self.y + height).contains(&y)
+    }
+}
+
+struct LayoutPixelMapper {
+    chain: usize,
+    parallel: usize,
+    panels: Vec<PanelPlacement>,
+}
+
+impl LayoutPixelMapper {
+    fn parse(layout: &str, chain: usize, parallel: usize) -> Result<Self, MatrixCreationError> {
+        let error = |line_number: usize, message: &str| {
+            MatrixCreationError::PixelMapperError(format!(
+                "LayoutMapper: Line {}: {message}",
+                line_number + 1
+            ))
+        };
+        let mut panels: Vec<PanelPlacement> = Vec::new();
+        for (line_number, line) in layout.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if !(4..=6).contains(&fields.len()) {
+                return Err(error(
+                    line_number,
+                    "Expected 'column row x y [rotation] [H|V]'.",
+                ));
+            }
+            let number = |field: &str| {
+                field
+                    .parse::<usize>()
+                    .map_err(|_| error(line_number, &format!("'{field}' is not a valid number.")))
+            };
+            let [column, row, x, y] = [
+                number(fields[0])?,
+                number(fields[1])?,
+                number(fields[2])?,
+                number(fields[3])?,
+            ];
+            let mut angle = 0;
+            let mut mirror = None;
+            for field in &fields[4..] {
+                match *field {
+                    "H" | "h" => mirror = Some(MirrorPixelMapper { horizontal: true }),
+                    "V" | "v" => mirror = Some(MirrorPixelMapper { horizontal: false }),
+                    field => {
+                        angle = number(field)?;
+                        if angle % 90 != 0 {
+                            return Err(error(
+                                line_number,
+                                &format!("'{angle}' is not a multiple of 90 degrees."),
+                            ));
+                        }
+                        angle %= 360;
+                    }
+                }
+            }
+            if column >= chain || row >= parallel {
+                return Err(error(
+                    line_number,
+                    &format!("There is no panel at column {column} and row {row}."),
+                ));
+            }
+            if panels
+                .iter()
+                .any(|panel| panel.column == column && panel.row == row)
+            {
+                return Err(error(
+                    line_number,
+                    &format!("The panel at column {column} and row {row} is placed twice."),
+                ));
+            }
+            panels.push(PanelPlacement {
+                column,
+                row,
+                x,
+                y,
+                mirror,
+                rotate: RotatePixelMapper { angle },
+            });
+        }
+        if panels.len() != chain * parallel {
+            let unused: Vec<String> = (0..parallel)
+                .flat_map(|row| (0..chain).map(move |column| (column, row)))
+                .filter(|&(column, row)| {
+                    !panels
+                        .iter()
+                        .any(|panel| panel.column == column && panel.row == row)
+                })
+                .map(|(column, row)| format!("({column}, {row})"))
+                .collect();
+            return Err(MatrixCreationError::PixelMapperError(format!(
+                "LayoutMapper: The panels at (column, row) {} are not placed.",
+                unused.join(", ")
+            )));
+        }
+        Ok(Self {
+            chain,
+            parallel,
+            panels,
+        })
+    }
+
+    fn panel_size(&self, matrix_width: usize, matrix_height: usize) -> [usize; 2] {
+        [matrix_width / self.chain, matrix_height / self.parallel]
+    }
+}
+
+impl NamedPixelMapper for LayoutPixelMapper {
+    fn get_size_mapping(
+        &self,
+        matrix_width: usize,
+        matrix_height: usize,
+    ) -> Result<[usize; 2], MatrixCreationError> {
+        let panel_size = self.panel_size(matrix_width, matrix_height);
+        let [visible_width, visible_height] =
+            self.panels.iter().fold([0, 0], |[width, height], panel| {
+                let [panel_width, panel_height] = panel.visible_size(panel_size[0], panel_size[1]);
+                [
+                    width.max(panel.x + panel_width),
+                    height.max(panel.y + panel_height),
+                ]
+            });
+        for y in 0..visible_height {
+            for x in 0..visible_width {
+                let mut covering = self
+                    .panels
+                    .iter()
+                    .filter(|panel| panel.contains(panel_size, x, y));
+                match (covering.next(), covering.next()) {
+                    (Some(_), None) => {}
+                    (None, _) => {
+                        return Err(MatrixCreationError::PixelMapperError(format!(
+                            "LayoutMapper: The canvas at ({x}, {y}) is not covered by any panel."
+                        )))
+                    }
+                    (Some(first), Some(second)) => {
+                        return Err(MatrixCreationError::PixelMapperError(format!(
+                            "LayoutMapper: The panels at (column, row) ({}, {}) and ({}, {}) \
+                            overlap at ({x}, {y}).",
+                            first.column, first.row, second.column, second.row
+                        )))
+                    }
+                }
+            }
+        }
+        Ok([visible_width, visible_height])
+    }
+
+    fn map_visible_to_matrix(
+        &self,
+        matrix_width: usize,
+        matrix_height: usize,
+        x: usize,
+        y: usize,
+    ) -> [usize; 2] {
+        let panel_size = self.panel_size(matrix_width, matrix_height);
+        let [panel_width, panel_height] = panel_size;
+        let panel = self
+            .panels
+            .iter()
+            .find(|panel| panel.contains(panel_size, x, y))
+            .expect("The layout covers the whole canvas.");
+        let [x, y] =
+            panel
+                .rotate
+                .map_visible_to_matrix(panel_width, panel_height, x - panel.x, y - panel.y);
+        let [x, y] = match &panel.mirror {
+            Some(mirror) => mirror.map_visible_to_matrix(panel_width, panel_height, x, y),
+            None => [x, y],
+        };
+        [panel.column * panel_width + x, panel.row * panel_height + y]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LayoutPixelMapper, NamedPixelMapper};
+    use crate::rgb_matrix::MatrixCreationError;
+
+    #[test]
+    fn test_layout_mapper() {
+        // Two chains of two 32x16 panels. The second chain is placed to the right, upright.
+        let layout = "
+            # column row x y rotation
+            0 0 0 0
+            1 0 0 16 180
+            0 1 32 0 90 H
+            1 1 48 0 270
+        ";
+        let mapper = LayoutPixelMapper::parse(layout, 2, 2).unwrap();
+        assert_eq!(mapper.get_size_mapping(64, 32).unwrap(), [64, 32]);
+        assert_eq!(mapper.map_visible_to_matrix(64, 32, 3, 4), [3, 4]);
+        assert_eq!(mapper.map_visible_to_matrix(64, 32, 0, 16), [63, 15]);
+        // Rotated by 90 degrees, then mirrored.
+        assert_eq!(mapper.map_visible_to_matrix(64, 32, 32, 0), [0, 16]);
+        assert_eq!(mapper.map_visible_to_matrix(64, 32, 47, 31), [31, 31]);
+        assert_eq!(mapper.map_visible_to_matrix(64, 32, 48, 0), [32, 31]);
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let message = |result: Result<[usize; 2], MatrixCreationError>| match result {
+            Err(MatrixCreationError::PixelMapperError(message)) => message,
+            other => panic!("unexpected {other:?}"),
+        };
+        let parse = |layout| LayoutPixelMapper::parse(layout, 2, 1).map(|_| [0, 0]);
+        assert!(message(parse("0 0 0 0")).contains("(1, 0) are not placed"));
+        assert!(message(parse("0 0 0 0\n0 0 0 16")).contains("placed twice"));
+        assert!(message(parse("0 0 0 0\n2 0 0 16")).contains("no panel"));
+        let size = |layout| {
+            LayoutPixelMapper::parse(layout, 2, 1)
+                .unwrap()
+                .get_size_mapping(64, 16)
+        };
+        assert!(message(size("0 0 0 0\n1 0 16 0")).contains("overlap at (16, 0)"));
+        assert!(message(size("0 0 0 0\n1 0 32 16")).contains("(32, 0) is not covered"));
+    }
+}
@@ -0,0 +1,579 @@
+use std::collections::VecDeque;
+
+use crate::{
+    canvas::{Channel, LedSequence},
+    color::inverse_luminance_cie1931,
+    config::SUB_PANELS,
+    hardware_mapping::ColorBits,
+    row_address_setter::RowAddressSetterType,
+    waveform::{Hub75Event, Hub75Trace},
+    Canvas, RGBMatrixConfig,
+};
+
+const LINE_A: u8 = 1 << 0;
+const LINE_B: u8 = 1 << 1;
+const LINE_C: u8 = 1 << 2;
+
+/// The image a panel showed during one frame, in matrix coordinates, i.e. columns in the order in which
+/// they are clocked in and rows as they are addressed. For multiplexed panels, this is the layout before
+/// the multiplex mapper is applied. [`PanelEmulator::visible_frame`] maps it to the visible coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmulatedFrame {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl EmulatedFrame {
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The perceived linear luminance of the red, green and blue LED of a pixel, relative to the time the
+    /// row was switched on.
+    #[must_use]
+    pub fn get_luminance(&self, x: usize, y: usize) -> Option<[f32; 3]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels.get(y * self.width + x).copied()
+    }
+
+    /// The color of a pixel with the CIE1931 luminance correction reverted, assuming full brightness.
+    #[must_use]
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        self.get_luminance(x, y)
+            .map(|luminance| luminance.map(inverse_luminance_cie1931))
+    }
+}
+
+/// Follows the address lines to find the row that the row address setter of the panel selected.
+enum RowDecoder {
+    /// The address lines hold the row.
+    Direct,
+    /// The row is selected by pulling one of the lines A to D low.
+    DirectABCDLine,
+    /// An active low bit is shifted in on the `data` line with A as the clock. The outputs are latched on
+    /// the same clock, one clock behind the shift register, so the extra clock after the address moves it
+    /// to the outputs.
+    ShiftRegister {
+        data: u8,
+        shifted: u64,
+        outputs: u64,
+    },
+    /// A high bit is shifted into the SM5266 shifters on B with A as the clock while C is high. D and E
+    /// select the shifter.
+    SM5266 { shifted: u8 },
+}
+
+impl RowDecoder {
+    fn new(row_setter: RowAddressSetterType) -> Self {
+        let shift_register = |data| RowDecoder::ShiftRegister {
+            data,
+            shifted: u64::MAX,
+            outputs: u64::MAX,
+        };
+        match row_setter {
+            RowAddressSetterType::Direct => RowDecoder::Direct,
+            RowAddressSetterType::DirectABCDLine => RowDecoder::DirectABCDLine,
+            RowAddressSetterType::ShiftRegister => shift_register(LINE_B),
+            RowAddressSetterType::ABCShiftRegister => shift_register(LINE_C),
+            RowAddressSetterType::SM5266 => RowDecoder::SM5266 { shifted: 0 },
+        }
+    }
+
+    /// Follow a change of the address lines.
+    fn update(&mut self, previous_address: u8, address: u8) {
+        let clock_edge = previous_address & LINE_A == 0 && address & LINE_A != 0;
+        match self {
+            RowDecoder::Direct | RowDecoder::DirectABCDLine => {}
+            RowDecoder::ShiftRegister {
+                data,
+                shifted,
+                outputs,
+            } => {
+                if clock_edge {
+                    *outputs = *shifted;
+                    *shifted = (*shifted << 1) | u64::from(address & *data != 0);
+                }
+            }
+            RowDecoder::SM5266 { shifted } => {
+                if clock_edge && address & LINE_C != 0 {
+                    *shifted = (*shifted << 1) | u8::from(address & LINE_B != 0);
+                }
+            }
+        }
+    }
+
+    /// The selected row, if exactly one row of the panel is selected.
+    fn row(&self, address: u8, double_rows: usize) -> Option<usize> {
+        let single_bit =
+            |bits: u64| (bits.count_ones() == 1).then(|| bits.trailing_zeros() as usize);
+        let row = match *self {
+            RowDecoder::Direct => usize::from(address),
+            RowDecoder::DirectABCDLine => single_bit(u64::from(!address & 0b1111))?,
+            RowDecoder::ShiftRegister { outputs, .. } => {
+                let row_mask = 1u64
+                    .checked_shl(double_rows as u32)
+                    .map_or(u64::MAX, |bit| bit - 1);
+                single_bit(!outputs & row_mask)?
+            }
+            RowDecoder::SM5266 { shifted } => {
+                single_bit(u64::from(shifted))? + 8 * usize::from((address >> 3) & 0b11)
+            }
+        };
+        (row < double_rows).then_some(row)
+    }
+}
+
+/// Emulates the shift register chain of HUB75 panels. It consumes the decoded signals of a
+/// [`Hub75Trace`] and reconstructs the image that the panels would have shown, weighting every latched row
+/// with the length of the output enable pulses. The rows are decoded from the address lines like the
+/// panels of the configured row address setter would do it.
+pub struct PanelEmulator {
+    width: usize,
+    rows: usize,
+    double_rows: usize,
+    parallel: usize,
+    color_bits: [ColorBits; 6],
+    led_sequence: LedSequence,
+    shift_register: VecDeque<u32>,
+    latched_row: Vec<u32>,
+    row_decoder: RowDecoder,
+    address: u8,
+    latched_address: Option<usize>,
+    on_time_ns: Vec<[u64; 3]>,
+    row_time_ns: Vec<u64>,
+    visited_rows: Vec<bool>,
+    frames: Vec<EmulatedFrame>,
+}
+
+impl PanelEmulator {
+    #[must_use]
+    pub fn new(config: &RGBMatrixConfig) -> Self {
+        let mut rows = config.rows;
+        let mut cols = config.cols;
+        if let Some(multiplexing) = config.multiplexing {
+            multiplexing.create().edit_rows_cols(&mut rows, &mut cols);
+        }
+        let width = cols * config.chain_length;
+        let height = rows * config.parallel;
+        let double_rows = rows / SUB_PANELS;
+        Self {
+            width,
+            rows,
+            double_rows,
+            parallel: config.parallel,
+            color_bits: config.hardware_mapping.panels.color_bits,
+            led_sequence: config.led_sequence,
+            shift_register: VecDeque::with_capacity(width + 1),
+            latched_row: vec![0; width],
+            row_decoder: RowDecoder::new(config.row_setter),
+            address: 0,
+            latched_address: None,
+            on_time_ns: vec![[0; 3]; width * height],
+            row_time_ns: vec![0; double_rows],
+            visited_rows: vec![false; double_rows],
+            frames: Vec::new(),
+        }
+    }
+
+    /// Feed all events of a trace into the emulator.
+    pub fn process(&mut self, trace: &Hub75Trace) {
+        trace
+            .events()
+            .iter()
+            .for_each(|event| self.process_event(event));
+    }
+
+    /// Feed a single event into the emulator. A frame is complete once a row is latched that was
+    /// already shown since the last frame was completed.
+    pub fn process_event(&mut self, event: &Hub75Event) {
+        match *event {
+            Hub75Event::Clock { levels, .. } => {
+                self.shift_register.push_back(levels);
+                if self.shift_register.len() > self.width {
+                    self.shift_register.pop_front();
+                }
+            }
+            Hub75Event::Address { address, .. } => {
+                self.row_decoder.update(self.address, address);
+                self.address = address;
+            }
+            Hub75Event::Latch { address, .. } => {
+                let row = self.row_decoder.row(address, self.double_rows);
+                if let Some(row) = row {
+                    if self.latched_address != Some(row) && self.visited_rows[row] {
+                        self.finish();
+                    }
+                }
+                let padding = self.width - self.shift_register.len();
+                self.latched_row.fill(0);
+                self.latched_row[padding..]
+                    .iter_mut()
+                    .zip(self.shift_register.iter())
+                    .for_each(|(latched, shifted)| *latched = *shifted);
+                self.latched_address = row;
+            }
+            Hub75Event::OutputEnable { duration_ns, .. } => {
+                let Some(address) = self.latched_address else {
+                    return;
+                };
+                self.visited_rows[address] = true;
+                self.row_time_ns[address] += duration_ns;
+                for (x, levels) in self.latched_row.iter().enumerate() {
+                    for (panel, bits) in self.color_bits.iter().take(self.parallel).enumerate() {
+                        let sub_panels = [
+                            (bits.r1, bits.g1, bits.b1, 0),
+                            (bits.r2, bits.g2, bits.b2, self.double_rows),
+                        ];
+                        for (r, g, b, row_offset) in sub_panels {
+                            let y = panel * self.rows + row_offset + address;
+                            let pixel = &mut self.on_time_ns[y * self.width + x];
+                            [Channel::First, Channel::Second, Channel::Third]
+                                .iter()
+                                .enumerate()
+                                .for_each(|(index, channel)| {
+                                    if levels & self.led_sequence.get_gpio(*channel, r, g, b) != 0 {
+                                        pixel[index] += duration_ns;
+                                    }
+                                });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Complete the current frame, if any row has been shown since the last frame was completed.
+    pub fn finish(&mut self) {
+        if !self.visited_rows.iter().any(|visited| *visited) {
+            return;
+        }
+        let height = self.rows * self.parallel;
+        let mut pixels = vec![[0.0; 3]; self.width * height];
+        for y in 0..height {
+            let row_time_ns = self.row_time_ns[(y % self.rows) % self.double_rows];
+            if row_time_ns == 0 {
+                continue;
+            }
+            for x in 0..self.width {
+                let position = y * self.width + x;
+                pixels[position] =
+                    self.on_time_ns[position].map(|on_time| on_time as f32 / row_time_ns as f32);
+            }
+        }
+        self.frames.push(EmulatedFrame {
+            width: self.width,
+            height,
+            pixels,
+        });
+        self.on_time_ns.fill([0; 3]);
+        self.row_time_ns.fill(0);
+        self.visited_rows.fill(false);
+    }
+
+    /// A frame in the visible coordinates of `canvas`, which has to be a canvas of the emulated matrix.
+    /// This reverts the multiplexing and the pixel mappers, so the frame can be compared with what was
+    /// drawn on the canvas.
+    #[must_use]
+    pub fn visible_frame(&self, frame: &EmulatedFrame, canvas: &Canvas) -> EmulatedFrame {
+        let width = canvas.width();
+        let height = canvas.height();
+        let mut pixels = vec![[0.0; 3]; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let Some((column, double_row, bits)) = canvas.matrix_designation(x, y) else {
+                    continue;
+                };
+                let matrix_y = self
+                    .color_bits
+                    .iter()
+                    .take(self.parallel)
+                    .enumerate()
+                    .find_map(|(panel, color_bits)| {
+                        let panel_y = panel * self.rows + double_row;
+                        if bits == color_bits.r1 | color_bits.g1 | color_bits.b1 {
+                            Some(panel_y)
+                        } else if bits == color_bits.r2 | color_bits.g2 | color_bits.b2 {
+                            Some(panel_y + self.double_rows)
+                        } else {
+                            None
+                        }
+                    });
+                if let Some(luminance) =
+                    matrix_y.and_then(|matrix_y| frame.get_luminance(column, matrix_y))
+                {
+                    pixels[y * width + x] = luminance;
+                }
+            }
+        }
+        EmulatedFrame {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// The frames that were completed so far.
+    #[must_use]
+    pub fn frames(&self) -> &[EmulatedFrame] {
+        &self.frames
+    }
+
+    /// Remove and return the frames that were completed so far.
+    pub fn take_frames(&mut self) -> Vec<EmulatedFrame> {
+        std::mem::take(&mut self.frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmulatedFrame, PanelEmulator};
+    use crate::{
+        simulated_backend::test_support::test_config, ColorCorrection, HardwareMapping,
+        LedSequence, MultiplexMapperType, RGBMatrix, RGBMatrixConfig, Reconfiguration,
+        RecordingRegisterBackend, RowAddressSetterType, SimulatedRegisterBackend,
+    };
+
+    /// Show the pixels on a simulated matrix and return the frame that the panels showed.
+    fn emulate(config: RGBMatrixConfig, pixels: &[(usize, usize, [u8; 3])]) -> EmulatedFrame {
+        let hardware_mapping = config.hardware_mapping;
+        let mut emulator = PanelEmulator::new(&config);
+        let backend = RecordingRegisterBackend::new(SimulatedRegisterBackend::new());
+        let recording = backend.recording();
+        let (mut matrix, mut canvas) =
+            RGBMatrix::new_with_backend(config, 0, move |_| Ok(backend)).unwrap();
+        for _ in 0..2 {
+            canvas.fill(0, 0, 0);
+            pixels
+                .iter()
+                .for_each(|&(x, y, [r, g, b])| canvas.set_pixel(x, y, r, g, b));
+            canvas = matrix.update_on_vsync(canvas).unwrap();
+        }
+        drop(matrix);
+        emulator.process(&recording.decode(&hardware_mapping));
+        emulator.finish();
+        let mut frames = emulator.take_frames();
+        // The last frame is the blank one shown on shutdown.
+        frames.pop();
+        frames.pop().unwrap()
+    }
+
+    fn lit_pixels(frame: &EmulatedFrame) -> Vec<(usize, usize, [u8; 3])> {
+        let mut lit = Vec::new();
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                let color = frame.get_pixel(x, y).unwrap();
+                if color != [0, 0, 0] {
+                    lit.push((x, y, color));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn test_parallel_chains() {
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::regular(),
+            rows: 16,
+            cols: 32,
+            chain_length: 2,
+            parallel: 2,
+            led_sequence: LedSequence::Grb,
+            ..test_config()
+        };
+        let pixels = [
+            (0, 0, [255, 0, 0]),
+            (63, 7, [0, 255, 0]),
+            (40, 8, [0, 0, 255]),
+            (5, 30, [255, 255, 255]),
+        ];
+        let frame = emulate(config, &pixels);
+        assert_eq!(lit_pixels(&frame), pixels);
+    }
+
+    #[test]
+    fn test_brightness_levels() {
+        // With and without hardware PWM, the output enable is pulsed by the PWM block or the timer.
+        for hardware_mapping in [
+            HardwareMapping::adafruit_hat_pwm(),
+            HardwareMapping::adafruit_hat(),
+        ] {
+            let config = RGBMatrixConfig {
+                hardware_mapping,
+                rows: 16,
+                cols: 16,
+                ..test_config()
+            };
+            let pixels = [(1, 1, [128, 64, 200]), (2, 9, [10, 20, 30])];
+            let frame = emulate(config, &pixels);
+            for (x, y, color) in pixels {
+                let shown = frame.get_pixel(x, y).unwrap();
+                color.iter().zip(shown).for_each(|(expected, shown)| {
+                    assert!(
+                        expected.abs_diff(shown) <= 1,
+                        "{color:?} shown as {shown:?}"
+                    );
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_row_address_setters() {
+        for (row_setter, rows) in [
+            (RowAddressSetterType::Direct, 32),
+            (RowAddressSetterType::ShiftRegister, 32),
+            (RowAddressSetterType::ABCShiftRegister, 32),
+            (RowAddressSetterType::SM5266, 64),
+            (RowAddressSetterType::DirectABCDLine, 8),
+        ] {
+            let config = RGBMatrixConfig {
+                hardware_mapping: HardwareMapping::regular(),
+                rows,
+                cols: 32,
+                row_setter,
+                ..test_config()
+            };
+            let pixels = [
+                (0, 0, [255, 0, 0]),
+                (7, 2, [0, 255, 0]),
+                (12, rows / 2 - 1, [0, 0, 255]),
+                (31, rows - 1, [255, 255, 255]),
+            ];
+            let frame = emulate(config, &pixels);
+            assert_eq!(lit_pixels(&frame), pixels, "{row_setter}");
+        }
+    }
+
+    #[test]
+    fn test_multiplexing() {
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::regular(),
+            rows: 32,
+            cols: 32,
+            multiplexing: Some(MultiplexMapperType::Stripe),
+            ..test_config()
+        };
+        let frame = emulate(
+            config,
+            &[
+                (0, 0, [255, 0, 0]),
+                (0, 8, [0, 255, 0]),
+                (5, 20, [0, 0, 255]),
+            ],
+        );
+        assert_eq!((frame.width(), frame.height()), (64, 16));
+        assert_eq!(
+            lit_pixels(&frame),
+            [
+                (0, 0, [0, 255, 0]),
+                (32, 0, [255, 0, 0]),
+                (37, 12, [0, 0, 255])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reconfigure() {
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::regular(),
+            rows: 16,
+            cols: 16,
+            ..test_config()
+        };
+        let hardware_mapping = config.hardware_mapping;
+        let mut emulator = PanelEmulator::new(&config);
+        let backend = RecordingRegisterBackend::new(SimulatedRegisterBackend::new());
+        let recording = backend.recording();
+        let (mut matrix, mut canvas) =
+            RGBMatrix::new_with_backend(config, 0, move |_| Ok(backend)).unwrap();
+        canvas.set_pixel(3, 3, 255, 255, 255);
+        canvas = matrix.update_on_vsync(canvas).unwrap();
+        matrix.reconfigure(Reconfiguration::Brightness(50)).unwrap();
+        matrix
+            .reconfigure(Reconfiguration::PwmLsbNanoseconds(260))
+            .unwrap();
+        matrix.reconfigure(Reconfiguration::DitherBits(1)).unwrap();
+        assert!(matrix.reconfigure(Reconfiguration::PwmBits(12)).is_err());
+        assert!(matrix.reconfigure(Reconfiguration::DitherBits(3)).is_err());
+        assert!(matrix.reconfigure(Reconfiguration::RefreshRate(0)).is_err());
+        // The canvas was drawn before the change, the matrix converts it before it is passed on.
+        canvas.set_pixel(3, 3, 255, 255, 255);
+        canvas = matrix.update_on_vsync(canvas).unwrap();
+        canvas = matrix.update_on_vsync(canvas).unwrap();
+        canvas.set_pixel(3, 3, 255, 255, 255);
+        let _canvas = matrix.update_on_vsync(canvas).unwrap();
+        drop(matrix);
+        emulator.process(&recording.decode(&hardware_mapping));
+        emulator.finish();
+        let frames = emulator.take_frames();
+        let luminance = |frame: &EmulatedFrame| frame.get_luminance(3, 3).unwrap()[0];
+        // The canvas that is shown during the change keeps its brightness until the next one is passed.
+        let converted = frames
+            .iter()
+            .position(|frame| luminance(frame) < 0.99)
+            .unwrap();
+        assert!(converted > 0);
+        for full in frames[..converted].iter().map(luminance) {
+            assert!((full - 1.0).abs() < 0.01, "{full}");
+        }
+        // Half the lightness in CIE1931.
+        let expected = (66.0f32 / 116.0).powi(3);
+        for frame in &frames[converted..frames.len() - 1] {
+            let [dimmed, ..] = frame.get_luminance(3, 3).unwrap();
+            assert!((dimmed - expected).abs() < 0.01, "{dimmed}");
+        }
+    }
+
+    #[test]
+    fn test_reconfigure_white_balance() {
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::regular(),
+            rows: 16,
+            cols: 16,
+            color_correction: ColorCorrection::Identity,
+            ..test_config()
+        };
+        let hardware_mapping = config.hardware_mapping;
+        let mut emulator = PanelEmulator::new(&config);
+        let backend = RecordingRegisterBackend::new(SimulatedRegisterBackend::new());
+        let recording = backend.recording();
+        let (mut matrix, mut canvas) =
+            RGBMatrix::new_with_backend(config, 0, move |_| Ok(backend)).unwrap();
+        let mut offscreen = matrix.create_offscreen_canvas();
+        // Both canvases were drawn before the change.
+        canvas.set_pixel(3, 3, 255, 255, 255);
+        offscreen.set_pixel(4, 4, 255, 255, 255);
+        matrix
+            .reconfigure(Reconfiguration::WhiteBalance("1,0.5,1".parse().unwrap()))
+            .unwrap();
+        let _canvas = matrix.update_on_vsync(canvas).unwrap();
+        let _offscreen = matrix.update_on_vsync(offscreen).unwrap();
+        drop(matrix);
+        emulator.process(&recording.decode(&hardware_mapping));
+        emulator.finish();
+        for (x, y) in [(3, 3), (4, 4)] {
+            let lit: Vec<[f32; 3]> = emulator
+                .frames()
+                .iter()
+                .filter_map(|frame| frame.get_luminance(x, y))
+                .filter(|[red, ..]| *red > 0.0)
+                .collect();
+            assert!(!lit.is_empty(), "({x}, {y}) was never shown.");
+            for [red, green, blue] in lit {
+                assert!((red - 1.0).abs() < 0.01, "{red}");
+                assert!((green - 0.5).abs() < 0.01, "{green}");
+                assert!((blue - 1.0).abs() < 0.01, "{blue}");
+            }
+        }
+    }
+}
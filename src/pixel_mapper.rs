@@ -3,6 +3,40 @@ use crate::{
     rgb_matrix::MatrixCreationError,
 };
 
+/// A pixel mapper that maps the visible canvas to the underlying matrix, for physical layouts that the
+/// built-in mappers can not express. Add it to the matrix with
+/// [`RGBMatrixBuilder::pixel_mapper`](crate::RGBMatrixBuilder::pixel_mapper).
+///
+/// Mappers are stacked: the matrix a mapper sees is the visible canvas of the previous one. Custom mappers
+/// always come after the multiplexing and all mappers of
+/// [`RGBMatrixConfig::pixelmapper`](crate::RGBMatrixConfig::pixelmapper), so they can not be placed between
+/// two named mappers. A named mapper that has to be applied after a custom one, e.g. a rotation, has to be
+/// implemented as a custom mapper as well.
+pub trait CustomPixelMapper {
+    /// The name of the mapper in error messages. Defaults to the name of the type.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Given a underlying matrix (width, height), returns the visible (width, height) after the mapping.
+    /// Return a [`MatrixCreationError::PixelMapperError`] if the mapper can not be used with this size.
+    fn get_size_mapping(
+        &self,
+        matrix_width: usize,
+        matrix_height: usize,
+    ) -> Result<[usize; 2], MatrixCreationError>;
+
+    /// Map where a visible pixel (x,y) is mapped to the underlying matrix (x,y). Creating the matrix fails
+    /// with a [`MatrixCreationError::PixelMapperError`] if a pixel is mapped outside of the matrix.
+    fn map_visible_to_matrix(
+        &self,
+        matrix_width: usize,
+        matrix_height: usize,
+        visible_x: usize,
+        visible_y: usize,
+    ) -> [usize; 2];
+}
+
 /// A pixel mapper is a way for you to map pixels of LED matrixes to a different
 /// layout. If you have an implementation of a [`PixelMapper`], you can give it
 /// to the `RGBMatrix::apply_pixel_mapper()`, which then presents you with a canvas
@@ -10,6 +44,7 @@ use crate::{
 pub(crate) enum PixelMapper {
     Multiplex(Box<dyn MultiplexMapper>),
     Named(Box<dyn NamedPixelMapper>),
+    Custom(Box<dyn CustomPixelMapper>),
 }
 
 impl PixelMapper {
@@ -25,6 +60,7 @@ impl PixelMapper {
         match self {
             PixelMapper::Multiplex(mapper) => mapper.get_size_mapping(matrix_width, matrix_height),
             PixelMapper::Named(mapper) => mapper.get_size_mapping(matrix_width, matrix_height),
+            PixelMapper::Custom(mapper) => mapper.get_size_mapping(matrix_width, matrix_height),
         }
     }
 
@@ -43,6 +79,110 @@ impl PixelMapper {
             PixelMapper::Named(mapper) => {
                 mapper.map_visible_to_matrix(matrix_width, matrix_height, visible_x, visible_y)
             }
+            PixelMapper::Custom(mapper) => {
+                mapper.map_visible_to_matrix(matrix_width, matrix_height, visible_x, visible_y)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CustomPixelMapper;
+    use crate::{
+        rgb_matrix::MatrixCreationError, simulated_backend::test_support::test_config,
+        HardwareMapping, PanelEmulator, RGBMatrixBuilder, RGBMatrixConfig,
+        RecordingRegisterBackend, SimulatedRegisterBackend,
+    };
+
+    /// Shows the second half of the chain below the first half.
+    struct StackMapper;
+
+    impl CustomPixelMapper for StackMapper {
+        fn get_size_mapping(
+            &self,
+            matrix_width: usize,
+            matrix_height: usize,
+        ) -> Result<[usize; 2], MatrixCreationError> {
+            Ok([matrix_width / 2, matrix_height * 2])
+        }
+
+        fn map_visible_to_matrix(
+            &self,
+            matrix_width: usize,
+            matrix_height: usize,
+            visible_x: usize,
+            visible_y: usize,
+        ) -> [usize; 2] {
+            let half = visible_y / matrix_height;
+            [
+                half * matrix_width / 2 + visible_x,
+                visible_y % matrix_height,
+            ]
+        }
+    }
+
+    /// Shifts the pixels one column to the right, past the end of the matrix.
+    struct ShiftMapper;
+
+    impl CustomPixelMapper for ShiftMapper {
+        fn get_size_mapping(
+            &self,
+            matrix_width: usize,
+            matrix_height: usize,
+        ) -> Result<[usize; 2], MatrixCreationError> {
+            Ok([matrix_width, matrix_height])
+        }
+
+        fn map_visible_to_matrix(
+            &self,
+            _matrix_width: usize,
+            _matrix_height: usize,
+            visible_x: usize,
+            visible_y: usize,
+        ) -> [usize; 2] {
+            [visible_x + 1, visible_y]
+        }
+    }
+
+    #[test]
+    fn test_custom_mapper_out_of_range() {
+        let config = test_config();
+        let result = RGBMatrixBuilder::new(config)
+            .pixel_mapper(ShiftMapper)
+            .build_with_backend(|_| Ok(SimulatedRegisterBackend::new()));
+        let Err(MatrixCreationError::PixelMapperError(message)) = result else {
+            panic!("The mapper was accepted.");
+        };
+        assert!(message.contains("ShiftMapper"), "{message}");
+        assert!(message.contains("(31, 0) to (32, 0)"), "{message}");
+    }
+
+    #[test]
+    fn test_custom_mapper() {
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::regular(),
+            chain_length: 2,
+            ..test_config()
+        };
+        let hardware_mapping = config.hardware_mapping;
+        let mut emulator = PanelEmulator::new(&config);
+        let backend = RecordingRegisterBackend::new(SimulatedRegisterBackend::new());
+        let recording = backend.recording();
+        let (mut matrix, mut canvas) = RGBMatrixBuilder::new(config)
+            .pixel_mapper(StackMapper)
+            .build_with_backend(move |_| Ok(backend))
+            .unwrap();
+        assert_eq!((canvas.width(), canvas.height()), (32, 32));
+        canvas.set_pixel(1, 20, 255, 255, 255);
+        canvas = matrix.update_on_vsync(canvas).unwrap();
+        drop(matrix);
+        emulator.process(&recording.decode(&hardware_mapping));
+        let frame = &emulator.frames()[0];
+        assert_eq!(frame.get_pixel(33, 4), Some([255, 255, 255]));
+        let visible = emulator.visible_frame(frame, &canvas);
+        assert_eq!((visible.width(), visible.height()), (32, 32));
+        assert_eq!(visible.get_pixel(1, 20), Some([255, 255, 255]));
+        assert_eq!(visible.get_pixel(1, 4), Some([0, 0, 0]));
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    error::Error,
+    ffi::{CStr, CString},
+    fmt::{Display, Formatter},
+    io,
+    str::FromStr,
+};
+
+/// `_LINUX_CAPABILITY_VERSION_3`, which uses two sets of 32 bits each.
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[repr(C)]
+struct CapabilityHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapabilityData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// How to give up root privileges once the update thread has initialized the hardware.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PrivilegeDrop {
+    /// Switch to another user and group. Without a group, the primary group of the user is used. Names and
+    /// numeric ids are both accepted.
+    User { user: String, group: Option<String> },
+    /// Keep the user but shed all capabilities of the calling thread, which includes the threads it spawns
+    /// afterwards, and of the update thread.
+    Capabilities,
+}
+
+impl FromStr for PrivilegeDrop {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("capabilities") {
+            return Ok(Self::Capabilities);
+        }
+        let (user, group) = match s.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (s, None),
+        };
+        if user.is_empty() || group.is_some_and(str::is_empty) {
+            return Err(format!("'{s}' is not a valid user or 'user:group'.").into());
+        }
+        Ok(Self::User {
+            user: user.to_string(),
+            group: group.map(str::to_string),
+        })
+    }
+}
+
+impl Display for PrivilegeDrop {
+    /// The privilege drop as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivilegeDrop::User {
+                user,
+                group: Some(group),
+            } => write!(f, "{user}:{group}"),
+            PrivilegeDrop::User { user, group: None } => f.write_str(user),
+            PrivilegeDrop::Capabilities => f.write_str("capabilities"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PrivilegeDropError {
+    UnknownUser(String),
+    UnknownGroup(String),
+    SystemCall(&'static str, io::Error),
+}
+
+impl Error for PrivilegeDropError {}
+
+impl Display for PrivilegeDropError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivilegeDropError::UnknownUser(user) => write!(f, "Unknown user '{user}'."),
+            PrivilegeDropError::UnknownGroup(group) => write!(f, "Unknown group '{group}'."),
+            PrivilegeDropError::SystemCall(call, error) => write!(f, "{call} failed: {error}"),
+        }
+    }
+}
+
+/// Check the return value of a libc call.
+fn check(call: &'static str, result: libc::c_int) -> Result<(), PrivilegeDropError> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(PrivilegeDropError::SystemCall(
+            call,
+            io::Error::last_os_error(),
+        ))
+    }
+}
+
+/// Look up a user by name or numeric id. Returns the name, if there is a passwd entry, the user id and
+/// the primary group id.
+fn lookup_user(
+    user: &str,
+) -> Result<(Option<CString>, libc::uid_t, Option<libc::gid_t>), PrivilegeDropError> {
+    let unknown_user = || PrivilegeDropError::UnknownUser(user.to_string());
+    let entry = if let Ok(uid) = user.parse::<libc::uid_t>() {
+        unsafe { libc::getpwuid(uid) }
+    } else {
+        let name = CString::new(user).map_err(|_| unknown_user())?;
+        unsafe { libc::getpwnam(name.as_ptr()) }
+    };
+    if entry.is_null() {
+        let uid = user.parse().map_err(|_| unknown_user())?;
+        return Ok((None, uid, None));
+    }
+    let entry = unsafe { &*entry };
+    let name = unsafe { CStr::from_ptr(entry.pw_name) }.to_owned();
+    Ok((Some(name), entry.pw_uid, Some(entry.pw_gid)))
+}
+
+/// Look up a group by name or numeric id.
+fn lookup_group(group: &str) -> Result<libc::gid_t, PrivilegeDropError> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let unknown_group = || PrivilegeDropError::UnknownGroup(group.to_string());
+    let name = CString::new(group).map_err(|_| unknown_group())?;
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(unknown_group());
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+/// Clear the effective, permitted and inheritable capabilities as well as the ambient capabilities of the
+/// calling thread. Capabilities are per thread, threads that are spawned afterwards inherit the empty
+/// sets.
+pub(crate) fn shed_capabilities() -> Result<(), PrivilegeDropError> {
+    check("prctl", unsafe {
+        libc::prctl(
+            libc::PR_CAP_AMBIENT,
+            libc::PR_CAP_AMBIENT_CLEAR_ALL,
+            0,
+            0,
+            0,
+        )
+    })?;
+    let mut header = CapabilityHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let data = [CapabilityData::default(); 2];
+    check("capset", unsafe {
+        libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) as libc::c_int
+    })
+}
+
+impl PrivilegeDrop {
+    /// Give up the privileges. Changing the user applies to all threads of the process.
+    pub(crate) fn apply(&self) -> Result<(), PrivilegeDropError> {
+        match self {
+            PrivilegeDrop::User { user, group } => {
+                let (name, uid, primary_gid) = lookup_user(user)?;
+                let gid = match group {
+                    Some(group) => lookup_group(group)?,
+                    None => {
+                        primary_gid.ok_or_else(|| PrivilegeDropError::UnknownUser(user.clone()))?
+                    }
+                };
+                // Keep the supplementary groups of the user, e.g. `gpio`.
+                match name {
+                    Some(name) => check("initgroups", unsafe {
+                        libc::initgroups(name.as_ptr(), gid)
+                    })?,
+                    None => check("setgroups", unsafe { libc::setgroups(1, &gid) })?,
+                }
+                check("setgid", unsafe { libc::setgid(gid) })?;
+                check("setuid", unsafe { libc::setuid(uid) })
+            }
+            PrivilegeDrop::Capabilities => shed_capabilities(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrivilegeDrop;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "capabilities".parse::<PrivilegeDrop>().unwrap(),
+            PrivilegeDrop::Capabilities
+        );
+        assert_eq!(
+            "pi".parse::<PrivilegeDrop>().unwrap(),
+            PrivilegeDrop::User {
+                user: "pi".to_string(),
+                group: None
+            }
+        );
+        assert_eq!(
+            "1000:gpio".parse::<PrivilegeDrop>().unwrap(),
+            PrivilegeDrop::User {
+                user: "1000".to_string(),
+                group: Some("gpio".to_string())
+            }
+        );
+        assert!("pi:".parse::<PrivilegeDrop>().is_err());
+        assert!(":gpio".parse::<PrivilegeDrop>().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{
+    chip::PiChip,
+    registers::{
+        ClkRegisters, GPIOFunction, GPIORegisters, MonotonicTimer, PWMRegisters, SystemTimer,
+        TimeRegisters, Timer, PWM_CTL_CLRF1, PWM_CTL_POLA1, PWM_CTL_PWEN1, PWM_CTL_USEF1,
+    },
+    rgb_matrix::MatrixCreationError,
+};
+
+/// Access to the peripheral registers that are needed to drive the matrix: the GPIO block, the system
+/// timer, the PWM block and its clock manager.
+///
+/// The update thread and the pin pulsers only ever talk to the hardware through this trait, so the matrix
+/// can be driven by something other than the memory mapped registers of a Raspberry Pi, e.g. the
+/// [`SimulatedRegisterBackend`](crate::SimulatedRegisterBackend).
+pub trait RegisterBackend {
+    /// Set the function of a GPIO pin.
+    fn select_function(&mut self, pin: u8, function: GPIOFunction);
+
+    /// Write to the `GPSET0` register. Pins with a 1 bit are driven high.
+    fn write_set_bits(&mut self, value: u32);
+
+    /// Write to the `GPCLR0` register. Pins with a 1 bit are driven low.
+    fn write_clr_bits(&mut self, value: u32);
+
+    /// Read the `GPLEV0` register.
+    fn read_pin_level0(&self) -> u32;
+
+    /// Time instant in microseconds.
+    fn get_time(&self) -> u64;
+
+    /// Time instant in nanoseconds, for backends that have a finer resolution than the system timer.
+    fn get_time_nanos(&self) -> u64 {
+        self.get_time() * 1000
+    }
+
+    /// Sleep for exactly this many microseconds.
+    fn sleep(&mut self, duration_us: u64);
+
+    /// Sleep for at most this many microseconds, leaving the rest of the time to be busy waited.
+    fn sleep_at_most(&mut self, duration_us: u64);
+
+    /// Sleep for a duration that is typically much shorter than a microsecond.
+    fn sleep_nanos(&mut self, duration_ns: u64);
+
+    /// Whether the output enable can be pulsed by the PWM block. If not, the output enable is always
+    /// pulsed by software and the PWM functions below are never called.
+    fn supports_hardware_pwm(&self) -> bool {
+        true
+    }
+
+    /// Write to the PWM control register.
+    fn set_pwm_ctl(&mut self, value: u32);
+
+    /// Write to the range register of PWM channel 1.
+    fn set_pwm_pulse_period(&mut self, value: u32);
+
+    /// Push a value into the PWM FIFO.
+    fn push_pwm_fifo(&mut self, value: u32);
+
+    /// Whether the PWM FIFO has been emptied.
+    fn pwm_fifo_empty(&self) -> bool;
+
+    /// Set the integer divider of the PWM clock, which runs off the 500 MHz PLLD.
+    fn init_pwm_divider(&mut self, divider: u32);
+
+    /// Channel 1: Use FIFO | Polarity (1=low, 0=high) | Enable Channel
+    fn enable_pwm(&mut self) {
+        self.set_pwm_ctl(PWM_CTL_USEF1 | PWM_CTL_POLA1 | PWM_CTL_PWEN1);
+    }
+
+    /// Channel 1: Use FIFO | Polarity (1=low, 0=high) | Clear FIFO
+    fn reset_pwm(&mut self) {
+        self.set_pwm_ctl(PWM_CTL_USEF1 | PWM_CTL_POLA1 | PWM_CTL_CLRF1);
+    }
+}
+
+/// The register backend of the Raspberry Pi models up to the Pi 4. The registers are memory mapped from
+/// `/dev/mem`, which requires root privileges.
+///
+/// Without root privileges, [`MmapRegisterBackend::new_unprivileged`] maps only the GPIO registers from
+/// `/dev/gpiomem` and measures time with `clock_gettime`. The PWM block is not available then, so the
+/// output enable has to be pulsed by software.
+pub struct MmapRegisterBackend {
+    gpio_registers: GPIORegisters,
+    timer: SystemTimer,
+    pwm: Option<(PWMRegisters, ClkRegisters)>,
+}
+
+impl MmapRegisterBackend {
+    /// Map all registers of the given chip.
+    pub fn new(chip: PiChip) -> Result<Self, MatrixCreationError> {
+        Ok(Self {
+            gpio_registers: GPIORegisters::new(chip)?,
+            timer: SystemTimer::Registers(TimeRegisters::new(chip)?),
+            pwm: Some((PWMRegisters::new(chip)?, ClkRegisters::new(chip)?)),
+        })
+    }
+
+    /// Map only the GPIO registers, which is possible for the members of the `gpio` group.
+    pub fn new_unprivileged(_chip: PiChip) -> Result<Self, MatrixCreationError> {
+        Ok(Self {
+            gpio_registers: GPIORegisters::new_gpiomem()?,
+            timer: SystemTimer::Monotonic(MonotonicTimer),
+            pwm: None,
+        })
+    }
+}
+
+impl RegisterBackend for MmapRegisterBackend {
+    #[inline]
+    fn select_function(&mut self, pin: u8, function: GPIOFunction) {
+        self.gpio_registers.select_function(pin, function);
+    }
+
+    #[inline]
+    fn write_set_bits(&mut self, value: u32) {
+        self.gpio_registers.write_set_bits(value);
+    }
+
+    #[inline]
+    fn write_clr_bits(&mut self, value: u32) {
+        self.gpio_registers.write_clr_bits(value);
+    }
+
+    #[inline]
+    fn read_pin_level0(&self) -> u32 {
+        self.gpio_registers.read_pin_level0()
+    }
+
+    #[inline]
+    fn get_time(&self) -> u64 {
+        self.timer.get_time()
+    }
+
+    fn sleep(&mut self, duration_us: u64) {
+        self.timer.sleep(duration_us);
+    }
+
+    fn sleep_at_most(&mut self, duration_us: u64) {
+        self.timer.sleep_at_most(duration_us);
+    }
+
+    fn sleep_nanos(&mut self, duration_ns: u64) {
+        self.timer.sleep_nanos(duration_ns);
+    }
+
+    fn supports_hardware_pwm(&self) -> bool {
+        self.pwm.is_some()
+    }
+
+    fn set_pwm_ctl(&mut self, value: u32) {
+        if let Some((pwm_registers, _)) = &mut self.pwm {
+            pwm_registers.set_pwm_ctl(value);
+        }
+    }
+
+    fn set_pwm_pulse_period(&mut self, value: u32) {
+        if let Some((pwm_registers, _)) = &mut self.pwm {
+            pwm_registers.set_pwm_pulse_period(value);
+        }
+    }
+
+    fn push_pwm_fifo(&mut self, value: u32) {
+        if let Some((pwm_registers, _)) = &mut self.pwm {
+            pwm_registers.push_fifo(value);
+        }
+    }
+
+    fn pwm_fifo_empty(&self) -> bool {
+        self.pwm
+            .as_ref()
+            .is_none_or(|(pwm_registers, _)| pwm_registers.fifo_empty())
+    }
+
+    fn init_pwm_divider(&mut self, divider: u32) {
+        if let Some((_, clk_registers)) = &mut self.pwm {
+            clk_registers.init_pwm_divider(divider);
+        }
+    }
+}
@@ -2,39 +2,43 @@ use std::{
     fs::OpenOptions,
     rc::Rc,
     thread::{sleep, yield_now},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use memmap2::{MmapMut, MmapOptions};
 
-use crate::chip::PiChip;
+use crate::{chip::PiChip, rgb_matrix::MatrixCreationError};
 
 // See https://elinux.org/BCM2835_registers
 
-struct MmapPtr<T> {
+pub(crate) struct MmapPtr<T> {
     ptr: *mut T,
     /// We need to hold on to the map.
     _map_ref: Rc<MmapMut>,
 }
 
 impl<T> MmapPtr<T> {
-    fn new(map: Rc<MmapMut>, byte_offset: usize) -> Self {
+    pub(crate) fn new(map: Rc<MmapMut>, byte_offset: usize) -> Self {
         let ptr = unsafe { map.as_ptr().add(byte_offset) as *mut T };
         Self { ptr, _map_ref: map }
     }
 
     #[inline(always)]
-    fn read(&self) -> T {
+    pub(crate) fn read(&self) -> T {
         unsafe { self.ptr.read_volatile() }
     }
 
     #[inline(always)]
-    fn write(&self, value: T) {
+    pub(crate) fn write(&self, value: T) {
         unsafe { self.ptr.write_volatile(value) }
     }
 }
 
+pub(crate) const MEM_PATH: &str = "/dev/mem";
+
 // General Purpose IO
+/// Maps only the GPIO registers and is accessible to the members of the `gpio` group.
+const GPIOMEM_PATH: &str = "/dev/gpiomem";
 const GP_OFFSET: u64 = 0x0020_0000;
 const GP_SIZE_BYTES: usize = 41 * std::mem::size_of::<u32>();
 const GP_FSEL0: usize = 0x0;
@@ -72,9 +76,9 @@ impl GPIOFunctionSelectRegisters {
     }
 }
 
-#[derive(Clone, Copy)]
-#[allow(unused)]
-pub(crate) enum GPIOFunction {
+/// The function a GPIO pin can be switched to with the function select registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GPIOFunction {
     Input,
     Output,
     Alt0,
@@ -86,7 +90,7 @@ pub(crate) enum GPIOFunction {
 }
 
 impl GPIOFunction {
-    fn bits(self) -> u32 {
+    pub(crate) fn bits(self) -> u32 {
         match self {
             GPIOFunction::Input => 0b000,
             GPIOFunction::Output => 0b001,
@@ -100,21 +104,34 @@ impl GPIOFunction {
     }
 }
 
-pub fn mmap_bcm_register(chip: PiChip, offset: u64, size_bytes: usize) -> Rc<MmapMut> {
+/// Memory map registers from a device file, `/dev/mem` or one of the `/dev/gpiomem` devices.
+pub(crate) fn mmap_register(
+    path: &str,
+    offset: u64,
+    size_bytes: usize,
+) -> Result<Rc<MmapMut>, MatrixCreationError> {
     let file = OpenOptions::new()
         .read(true)
         .write(true)
-        .open("/dev/mem")
-        .expect("Failed to open '/dev/mem'");
-    let base = chip.get_peripherals_base();
+        .open(path)
+        .map_err(|_| MatrixCreationError::MemoryAccessError)?;
     let map = unsafe {
         MmapOptions::new()
-            .offset(base + offset)
+            .offset(offset)
             .len(size_bytes)
             .map_mut(&file)
-            .unwrap()
+            .map_err(|_| MatrixCreationError::MemoryAccessError)?
     };
-    Rc::new(map)
+    Ok(Rc::new(map))
+}
+
+/// Memory map peripheral registers from `/dev/mem`, which requires root privileges.
+pub(crate) fn mmap_bcm_register(
+    chip: PiChip,
+    offset: u64,
+    size_bytes: usize,
+) -> Result<Rc<MmapMut>, MatrixCreationError> {
+    mmap_register(MEM_PATH, chip.get_peripherals_base() + offset, size_bytes)
 }
 
 pub(crate) struct GPIORegisters {
@@ -125,8 +142,16 @@ pub(crate) struct GPIORegisters {
 }
 
 impl GPIORegisters {
-    pub(crate) fn new(chip: PiChip) -> Self {
-        let map = mmap_bcm_register(chip, GP_OFFSET, GP_SIZE_BYTES);
+    pub(crate) fn new(chip: PiChip) -> Result<Self, MatrixCreationError> {
+        Ok(Self::from_map(mmap_bcm_register(chip, GP_OFFSET, GP_SIZE_BYTES)?))
+    }
+
+    /// Map the GPIO registers from `/dev/gpiomem`, which starts at the GPIO block.
+    pub(crate) fn new_gpiomem() -> Result<Self, MatrixCreationError> {
+        Ok(Self::from_map(mmap_register(GPIOMEM_PATH, 0, GP_SIZE_BYTES)?))
+    }
+
+    fn from_map(map: Rc<MmapMut>) -> Self {
         let clr0 = MmapPtr::new(map.clone(), GP_CLR0);
         let set0 = MmapPtr::new(map.clone(), GP_SET0);
         let lvl0 = MmapPtr::new(map.clone(), GP_LEV0);
@@ -162,6 +187,10 @@ const ST_SIZE_BYTES: usize = 28;
 const ST_CLO: usize = 0x4;
 
 const MIN_SYS_SLEEP_TIME_US: u64 = 100;
+/// The fraction of a sleep that is handed to the system sleep, the rest is spent busy waiting.
+const SLEEP_FACTOR: f32 = 0.4;
+const EMPIRICAL_NANOSLEEP_OVERHEAD_US: u64 = 12;
+const MINIMUM_NANOSLEEP_TIME_US: u64 = 5;
 
 /// Required to read `ST_CLO` and the adjacent `ST_CHI`.
 /// This has to be a struct so that we can have a fixed memory layout.
@@ -177,38 +206,92 @@ impl TimeRegister {
     }
 }
 
+/// A microsecond timer. Sleeping is implemented on top of reading the current time.
+pub(crate) trait Timer {
+    /// Time instant in microseconds.
+    fn get_time(&self) -> u64;
+
+    fn sleep(&mut self, duration_us: u64) {
+        let end_time = self.get_time() + duration_us;
+        self.sleep_at_most(duration_us);
+        while self.get_time() < end_time {
+            yield_now();
+        }
+    }
+
+    fn sleep_at_most(&mut self, duration_us: u64) {
+        if duration_us > MIN_SYS_SLEEP_TIME_US {
+            let sys_sleep_time = (duration_us as f32 * SLEEP_FACTOR) as u64;
+            sleep(Duration::from_micros(sys_sleep_time));
+        }
+    }
+
+    /// Sleep for a short duration. Longer durations are handed to the system sleep, the remaining time
+    /// is spent busy waiting to avoid the scheduling jitter.
+    fn sleep_nanos(&mut self, mut duration_ns: u64) {
+        let start = Instant::now();
+        let jitter_allowance_ns = (EMPIRICAL_NANOSLEEP_OVERHEAD_US + 10) * 1000;
+        if duration_ns > jitter_allowance_ns + MINIMUM_NANOSLEEP_TIME_US * 1000 {
+            sleep(Duration::from_nanos(duration_ns - jitter_allowance_ns));
+            let passed_ns = start.elapsed().as_nanos() as u64;
+            if passed_ns > duration_ns {
+                // Missed it.
+                return;
+            }
+            duration_ns -= passed_ns;
+        }
+        let end = Instant::now() + Duration::from_nanos(duration_ns);
+        while Instant::now() < end {
+            std::hint::spin_loop();
+        }
+    }
+}
+
 // Time measurement.
 pub(crate) struct TimeRegisters {
     time: MmapPtr<TimeRegister>,
-    sleep_factor: f32,
 }
 
 impl TimeRegisters {
-    pub(crate) fn new(chip: PiChip) -> Self {
-        let map = mmap_bcm_register(chip, ST_OFFSET, ST_SIZE_BYTES);
+    pub(crate) fn new(chip: PiChip) -> Result<Self, MatrixCreationError> {
+        let map = mmap_bcm_register(chip, ST_OFFSET, ST_SIZE_BYTES)?;
         let time = MmapPtr::new(map, ST_CLO);
-        Self {
-            time,
-            sleep_factor: 0.4,
-        }
+        Ok(Self { time })
     }
+}
 
-    pub(crate) fn get_time(&self) -> u64 {
+impl Timer for TimeRegisters {
+    fn get_time(&self) -> u64 {
         self.time.read().get_u64()
     }
+}
 
-    pub(crate) fn sleep(&mut self, duration_us: u64) {
-        let end_time = self.get_time() + duration_us;
-        self.sleep_at_most(duration_us);
-        while self.get_time() < end_time {
-            yield_now();
-        }
+/// Time measurement with `clock_gettime`, for when the system timer is not available.
+pub(crate) struct MonotonicTimer;
+
+impl Timer for MonotonicTimer {
+    fn get_time(&self) -> u64 {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+        time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1000
     }
+}
 
-    pub(crate) fn sleep_at_most(&mut self, duration_us: u64) {
-        if duration_us > MIN_SYS_SLEEP_TIME_US {
-            let sys_sleep_time = (duration_us as f32 * self.sleep_factor) as u64;
-            sleep(Duration::from_micros(sys_sleep_time));
+/// The system timer registers if they could be mapped, `clock_gettime` otherwise.
+pub(crate) enum SystemTimer {
+    Registers(TimeRegisters),
+    Monotonic(MonotonicTimer),
+}
+
+impl Timer for SystemTimer {
+    #[inline]
+    fn get_time(&self) -> u64 {
+        match self {
+            SystemTimer::Registers(time_registers) => time_registers.get_time(),
+            SystemTimer::Monotonic(monotonic_timer) => monotonic_timer.get_time(),
         }
     }
 }
@@ -239,28 +322,18 @@ pub(crate) struct PWMRegisters {
 }
 
 impl PWMRegisters {
-    pub(crate) fn new(chip: PiChip) -> Self {
-        let map = mmap_bcm_register(chip, PWM_OFFSET, PWM_SIZE_BYTES);
+    pub(crate) fn new(chip: PiChip) -> Result<Self, MatrixCreationError> {
+        let map = mmap_bcm_register(chip, PWM_OFFSET, PWM_SIZE_BYTES)?;
         let ctl = MmapPtr::new(map.clone(), PWM_CTL);
         let rng1 = MmapPtr::new(map.clone(), PWM_RNG1);
         let fif1 = MmapPtr::new(map.clone(), PWM_FIF1);
         let sta = MmapPtr::new(map, PWM_STA);
-        Self {
+        Ok(Self {
             ctl,
             rng1,
             fif1,
             sta,
-        }
-    }
-
-    /// Channel 1: Use FIFO | Polarity (1=low, 0=high) | Enable Channel
-    pub(crate) fn enable_pwm(&mut self) {
-        self.set_pwm_ctl(PWM_CTL_USEF1 | PWM_CTL_POLA1 | PWM_CTL_PWEN1);
-    }
-
-    /// Channel 1: Use FIFO | Polarity (1=low, 0=high) | Clear FIFO
-    pub(crate) fn reset_pwm(&mut self) {
-        self.set_pwm_ctl(PWM_CTL_USEF1 | PWM_CTL_POLA1 | PWM_CTL_CLRF1);
+        })
     }
 
     pub(crate) fn set_pwm_ctl(&mut self, value: u32) {
@@ -316,11 +389,11 @@ pub(crate) struct ClkRegisters {
 }
 
 impl ClkRegisters {
-    pub(crate) fn new(chip: PiChip) -> Self {
-        let map = mmap_bcm_register(chip, CM_OFFSET, CM_SIZE_BYTES);
+    pub(crate) fn new(chip: PiChip) -> Result<Self, MatrixCreationError> {
+        let map = mmap_bcm_register(chip, CM_OFFSET, CM_SIZE_BYTES)?;
         let pwm_ctl = MmapPtr::new(map.clone(), CM_PWMCTL);
         let pwm_div = MmapPtr::new(map, CM_PWMDIV);
-        Self { pwm_ctl, pwm_div }
+        Ok(Self { pwm_ctl, pwm_div })
     }
 
     pub(crate) fn init_pwm_divider(&mut self, divider: u32) {
@@ -1,73 +1,53 @@
 use std::{
+    any::Any,
     error::Error,
-    fmt::{Display, Formatter},
-    fs::{write, OpenOptions},
+    fmt::{Debug, Display, Formatter},
+    fs::OpenOptions,
     mem::replace,
-    sync::mpsc::{
-        channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{
+            channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError,
+            TrySendError,
+        },
+        Arc, Mutex, PoisonError,
     },
     thread::{spawn, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use thread_priority::{set_current_thread_priority, ThreadPriority};
-
 use crate::{
+    async_update::{InputStream, UpdateFuture, WakerSlot},
     canvas::{Canvas, PixelDesignator, PixelDesignatorMap},
     chip::PiChip,
+    color::{ColorCorrection, ColorLookup, WhiteBalance},
+    config::K_BIT_PLANES,
+    exit_guard::ExitGuard,
     gpio::{Gpio, GpioInitializationError},
-    pixel_mapper::PixelMapper,
-    utils::{linux_has_isol_cpu, set_thread_affinity, FrameRateMonitor},
+    logging::{log_info, log_warn},
+    pixel_mapper::{CustomPixelMapper, PixelMapper},
+    privileges::{shed_capabilities, PrivilegeDrop, PrivilegeDropError},
+    register_backend::{MmapRegisterBackend, RegisterBackend},
+    registers::MEM_PATH,
+    rp1_registers::Rp1RegisterBackend,
+    system_tuning::SystemTuning,
+    terminal_preview::{PreviewRegisterBackend, TerminalPreview},
+    utils::FrameRateMonitor,
     RGBMatrixConfig,
 };
 
-fn initialize_update_thread(chip: PiChip) {
-    // Pin the thread to the last core to avoid the flicker resulting from context switching.
-    let last_core_id = chip.num_cores() - 1;
-    set_thread_affinity(last_core_id);
-
-    // If the user has not setup isolcpus, let them know about the performance improvement.
-    if chip.num_cores() > 1 && !linux_has_isol_cpu(last_core_id) {
-        eprintln!(
-            "Suggestion: to slightly improve display update, add\n\tisolcpus={last_core_id}\nat \
-            the end of /boot/cmdline.txt and reboot"
-        );
-    }
-
-    // Disable realtime throttling.
-    if chip.num_cores() > 1 && write("/proc/sys/kernel/sched_rt_runtime_us", "999000").is_err() {
-        eprintln!("Could not disable realtime throttling");
-    }
-
-    // Set the last core to performance mode.
-    if chip.num_cores() > 1
-        && write(
-            format!("/sys/devices/system/cpu/cpu{last_core_id}/cpufreq/scaling_governor"),
-            "performance",
-        )
-        .is_err()
-    {
-        eprintln!(
-            "Could not set core {} to performance mode.",
-            last_core_id + 1
-        );
-    }
-
-    // Set the highest thread priority.
-    if set_current_thread_priority(ThreadPriority::Max).is_err() {
-        eprintln!("Could not set thread priority. This might lead to reduced performance.",);
-    }
-}
-
 #[derive(Debug)]
 pub enum MatrixCreationError {
     ChipDeterminationError,
     TooManyParallelChains(usize),
     InvalidDitherBits(usize),
+    InvalidCpuCore(usize),
     ThreadTimedOut,
     GpioError(GpioInitializationError),
     MemoryAccessError,
+    PwmAccessError,
     PixelMapperError(String),
+    PrivilegeDropError(PrivilegeDropError),
 }
 
 impl Error for MatrixCreationError {}
@@ -84,6 +64,9 @@ impl Display for MatrixCreationError {
             MatrixCreationError::InvalidDitherBits(value) => {
                 write!(f, "Unsupported dither bits '{value}'.")
             }
+            MatrixCreationError::InvalidCpuCore(core) => {
+                write!(f, "The Raspberry Pi has no CPU core {core}.")
+            }
             MatrixCreationError::ThreadTimedOut => {
                 f.write_str("The update thread did not return in time.")
             }
@@ -91,30 +74,301 @@ impl Display for MatrixCreationError {
                 write!(f, "GPIO initialization error: {error}")
             }
             MatrixCreationError::MemoryAccessError => f.write_str(
-                "Failed to access the physical memory. Not running with root privileges?",
+                "Failed to access the physical memory. Not running with root privileges or as a \
+                member of the gpio group?",
+            ),
+            MatrixCreationError::PwmAccessError => f.write_str(
+                "The hardware mapping pulses the output enable with the PWM block, which can only be \
+                accessed with root privileges. Run as root or use a mapping without hardware PWM.",
             ),
             MatrixCreationError::PixelMapperError(message) => {
                 write!(f, "Error in pixel mapper: {message}")
             }
+            MatrixCreationError::PrivilegeDropError(error) => {
+                write!(f, "Failed to drop privileges: {error}")
+            }
         }
     }
 }
 
+/// A setting of the update thread that can be changed while the matrix is running, see
+/// [`RGBMatrix::reconfigure`].
+#[derive(Clone, Debug)]
+pub enum Reconfiguration {
+    /// Brightness in percent, like [`RGBMatrixConfig::led_brightness`].
+    Brightness(u8),
+    /// Like [`RGBMatrixConfig::pwm_bits`].
+    PwmBits(usize),
+    /// Like [`RGBMatrixConfig::dither_bits`].
+    DitherBits(usize),
+    /// Like [`RGBMatrixConfig::pwm_lsb_nanoseconds`].
+    PwmLsbNanoseconds(u32),
+    /// Like [`RGBMatrixConfig::refresh_rate`].
+    RefreshRate(usize),
+    /// Like [`RGBMatrixConfig::color_correction`].
+    ColorCorrection(ColorCorrection),
+    /// Like [`RGBMatrixConfig::white_balance`].
+    WhiteBalance(WhiteBalance),
+}
+
+#[derive(Debug)]
+pub enum ReconfigurationError {
+    InvalidPwmBits(usize),
+    InvalidDitherBits(usize),
+    InvalidRefreshRate(usize),
+    /// The update thread stopped.
+    Stopped(UpdateError),
+}
+
+impl Error for ReconfigurationError {}
+
+impl Display for ReconfigurationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReconfigurationError::InvalidPwmBits(value) => {
+                write!(
+                    f,
+                    "Unsupported PWM bits '{value}'. Valid values are 1 to {K_BIT_PLANES}."
+                )
+            }
+            ReconfigurationError::InvalidDitherBits(value) => {
+                write!(f, "Unsupported dither bits '{value}'.")
+            }
+            ReconfigurationError::InvalidRefreshRate(value) => {
+                write!(f, "Unsupported refresh rate '{value}'.")
+            }
+            ReconfigurationError::Stopped(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+/// Why the update thread stopped, see [`RGBMatrix::thread_state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdateError {
+    /// The update thread panicked with this message.
+    ThreadPanicked(String),
+    /// The update thread exited without panicking.
+    ThreadExited,
+}
+
+impl Error for UpdateError {}
+
+impl Display for UpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateError::ThreadPanicked(message) => {
+                write!(f, "The display update thread panicked: {message}")
+            }
+            UpdateError::ThreadExited => {
+                f.write_str("The display update thread shut down unexpectedly.")
+            }
+        }
+    }
+}
+
+/// The error of [`RGBMatrix::try_update`].
+pub enum TryUpdateError {
+    /// The update thread can not take the canvas right now, so it is returned.
+    Full(Box<Canvas>),
+    /// The update thread stopped.
+    Stopped(UpdateError),
+}
+
+impl Debug for TryUpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryUpdateError::Full(_) => f.write_str("Full(..)"),
+            TryUpdateError::Stopped(error) => f.debug_tuple("Stopped").field(error).finish(),
+        }
+    }
+}
+
+impl Error for TryUpdateError {}
+
+impl Display for TryUpdateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryUpdateError::Full(_) => {
+                f.write_str("The display update thread can not take the canvas.")
+            }
+            TryUpdateError::Stopped(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+/// The state of the update thread, see [`RGBMatrix::thread_state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ThreadState {
+    /// The canvases are shown.
+    Running,
+    /// The panels were blanked because no canvas was passed within
+    /// [`RGBMatrixConfig::watchdog_timeout_ms`]. The next canvas is shown again.
+    WatchdogBlanked,
+    /// The update thread stopped and the panels are no longer driven.
+    Stopped(UpdateError),
+}
+
+/// The message a thread panicked with.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown cause".to_string()
+    }
+}
+
+/// The lowest bit plane that is shown in each of four consecutive frames.
+fn dither_start_bits(dither_bits: usize) -> Option<[usize; 4]> {
+    match dither_bits {
+        0 => Some([0, 0, 0, 0]),
+        1 => Some([0, 1, 0, 1]),
+        2 => Some([0, 1, 2, 2]),
+        _ => None,
+    }
+}
+
+fn frame_time_target_us(refresh_rate: usize) -> u64 {
+    (1_000_000.0 / refresh_rate as f64) as u64
+}
+
 pub struct RGBMatrix {
     /// The join handle of the update thread.
     thread_handle: Option<JoinHandle<()>>,
     /// Sender for the shutdown signal.
     shutdown_sender: Sender<()>,
-    /// Receiver for GPIO inputs.
-    input_receiver: Receiver<u32>,
-    /// Channel to send canvas to update thread.
-    canvas_to_thread_sender: SyncSender<Box<Canvas>>,
+    /// Sender for changes of the settings of the update thread.
+    reconfiguration_sender: Sender<Reconfiguration>,
+    /// Counts the reconfigurations that change how the canvases are converted.
+    reconfiguration_generation: u64,
+    /// The settings the canvases are converted to before they are passed to the update thread.
+    brightness: u8,
+    pwm_bits: usize,
+    color_correction: ColorCorrection,
+    white_balance: WhiteBalance,
+    /// Computed from the color correction and the white balance, shared by all canvases.
+    color_lookup: Arc<ColorLookup>,
+    /// Receiver for GPIO inputs, shared with the input streams.
+    input_receiver: Arc<Mutex<Receiver<u32>>>,
+    /// Woken by the update thread when the inputs changed.
+    input_waker: Arc<WakerSlot>,
+    /// Channel to send canvas to update thread, with the number of frames to show it.
+    pub(crate) canvas_to_thread_sender: SyncSender<(Box<Canvas>, usize)>,
     /// Channel to receive canvas from update thread.
-    canvas_from_thread_receiver: Receiver<Box<Canvas>>,
+    pub(crate) canvas_from_thread_receiver: Receiver<Box<Canvas>>,
+    /// Woken by the update thread when it waits for a canvas or returned one.
+    pub(crate) update_waker: Arc<WakerSlot>,
     /// Additional requested inputs that can be received.
     enabled_input_bits: u32,
+    /// A blank canvas to create offscreen canvases from.
+    blank_canvas: Box<Canvas>,
+    /// Canvases of cancelled asynchronous updates, reused for offscreen canvases.
+    pub(crate) spare_canvases: Vec<Canvas>,
+    /// The number of canvases the update thread still returns for cancelled asynchronous updates.
+    pub(crate) owed_canvases: usize,
     /// Frame rate measurement.
-    frame_rate_monitor: FrameRateMonitor,
+    pub(crate) frame_rate_monitor: FrameRateMonitor,
+    /// The number of frames the update thread has shown.
+    refresh_count: Arc<AtomicU64>,
+    /// Whether the watchdog of the update thread blanked the panels.
+    watchdog_blanked: Arc<AtomicBool>,
+    /// Why the update thread stopped, once it was joined.
+    thread_error: Option<UpdateError>,
+}
+
+/// Builder for an [`RGBMatrix`], for the options that can not be part of the [`RGBMatrixConfig`].
+pub struct RGBMatrixBuilder {
+    config: RGBMatrixConfig,
+    requested_inputs: u32,
+    pixel_mappers: Vec<Box<dyn CustomPixelMapper>>,
+    queue_depth: usize,
+    blank_on_exit: bool,
+    blank_on_panic: bool,
+}
+
+impl RGBMatrixBuilder {
+    #[must_use]
+    pub fn new(config: RGBMatrixConfig) -> Self {
+        Self {
+            config,
+            requested_inputs: 0,
+            pixel_mappers: Vec::new(),
+            queue_depth: 0,
+            blank_on_exit: false,
+            blank_on_panic: false,
+        }
+    }
+
+    /// Request user readable GPIO bits, see [`RGBMatrix::new`].
+    #[must_use]
+    pub fn requested_inputs(mut self, requested_inputs: u32) -> Self {
+        self.requested_inputs = requested_inputs;
+        self
+    }
+
+    /// Add a pixel mapper. Mappers are applied in the order they are added, after the multiplexing and the
+    /// whole [`RGBMatrixConfig::pixelmapper`] list. They can not be placed between the named mappers of that
+    /// list.
+    #[must_use]
+    pub fn pixel_mapper(mut self, mapper: impl CustomPixelMapper + 'static) -> Self {
+        self.pixel_mappers.push(Box::new(mapper));
+        self
+    }
+
+    /// Set the number of canvases that can wait for the update thread. With the default of 0, a canvas is
+    /// handed over to the update thread directly at the end of a frame. With a deeper queue, e.g. 1 for
+    /// triple buffering, the next frames can be rendered while the current one is shown.
+    #[must_use]
+    pub fn queue_depth(mut self, queue_depth: usize) -> Self {
+        self.queue_depth = queue_depth;
+        self
+    }
+
+    /// Blank the panels before the process exits on SIGINT, SIGTERM, SIGHUP or SIGQUIT. Otherwise only
+    /// dropping the matrix blanks them, and the LEDs stay frozen on one row when the process is terminated.
+    ///
+    /// This installs signal handlers for the lifetime of the matrix, which replace handlers that were
+    /// installed before. Signals that are ignored, e.g. SIGHUP under `nohup`, stay ignored. On a signal, the
+    /// update thread stops and the previous handlers are restored before the signal is raised again.
+    ///
+    /// Panics are not covered, since they do not necessarily end the process. See
+    /// [`RGBMatrixBuilder::blank_on_panic`] for that.
+    #[must_use]
+    pub fn blank_on_exit(mut self, blank_on_exit: bool) -> Self {
+        self.blank_on_exit = blank_on_exit;
+        self
+    }
+
+    /// Blank the panels and stop the update thread when any other thread panics, before the process may
+    /// exit or abort.
+    ///
+    /// This installs a panic hook that calls the previous hook. It reacts to every panic, including those
+    /// that are caught, e.g. by an async runtime or a web framework, and makes the panicking thread wait up
+    /// to a second until the panels are blank. After that, the swaps return
+    /// [`UpdateError::ThreadExited`]. Only enable it if a panic ends the program.
+    #[must_use]
+    pub fn blank_on_panic(mut self, blank_on_panic: bool) -> Self {
+        self.blank_on_panic = blank_on_panic;
+        self
+    }
+
+    /// Create the matrix like [`RGBMatrix::new`].
+    pub fn build(self) -> Result<(RGBMatrix, Box<Canvas>), MatrixCreationError> {
+        RGBMatrix::create_with_default_backend(self)
+    }
+
+    /// Create the matrix like [`RGBMatrix::new_with_backend`].
+    pub fn build_with_backend<B, F>(
+        self,
+        create_backend: F,
+    ) -> Result<(RGBMatrix, Box<Canvas>), MatrixCreationError>
+    where
+        B: RegisterBackend,
+        F: FnOnce(PiChip) -> Result<B, MatrixCreationError> + Send + 'static,
+    {
+        RGBMatrix::create(self, create_backend)
+    }
 }
 
 impl RGBMatrix {
@@ -125,23 +379,109 @@ impl RGBMatrix {
     /// [`RGBMatrix::receive_new_inputs`]. Only bits that are not already in use for reading or writing by the
     /// matrix are allowed. Use [`RGBMatrix::enabled_input_bits`] after calling this function to check which
     /// bits were actually available.
+    ///
+    /// Without root privileges, the GPIO registers are mapped from `/dev/gpiomem` instead of `/dev/mem`,
+    /// which only requires membership in the `gpio` group. Hardware mappings that pulse the output enable
+    /// with the PWM block can not be used then.
     pub fn new(
-        mut config: RGBMatrixConfig,
+        config: RGBMatrixConfig,
         requested_inputs: u32,
     ) -> Result<(Self, Box<Canvas>), MatrixCreationError> {
-        // Check if we can access the memory before doing anything else.
-        OpenOptions::new()
+        RGBMatrixBuilder::new(config)
+            .requested_inputs(requested_inputs)
+            .build()
+    }
+
+    /// Create a new RGB matrix controller that drives the matrix through a custom [`RegisterBackend`]. The
+    /// backend is created by `create_backend` on the update thread, since register mappings are usually
+    /// bound to the thread that created them.
+    ///
+    /// The chip can not be determined automatically on machines other than a Raspberry Pi, so
+    /// [`RGBMatrixConfig::pi_chip`] has to be set in that case.
+    pub fn new_with_backend<B, F>(
+        config: RGBMatrixConfig,
+        requested_inputs: u32,
+        create_backend: F,
+    ) -> Result<(Self, Box<Canvas>), MatrixCreationError>
+    where
+        B: RegisterBackend,
+        F: FnOnce(PiChip) -> Result<B, MatrixCreationError> + Send + 'static,
+    {
+        RGBMatrixBuilder::new(config)
+            .requested_inputs(requested_inputs)
+            .build_with_backend(create_backend)
+    }
+
+    /// Pick the register backend that matches the chip and the available privileges.
+    fn create_with_default_backend(
+        mut builder: RGBMatrixBuilder,
+    ) -> Result<(Self, Box<Canvas>), MatrixCreationError> {
+        let config = &mut builder.config;
+        if config.preview {
+            // Nothing depends on the chip without hardware, so do not require a Raspberry Pi.
+            config.pi_chip.get_or_insert(PiChip::BCM2708);
+            return Self::create(builder, PreviewRegisterBackend::new);
+        }
+
+        let chip = if let Some(chip) = config.pi_chip {
+            chip
+        } else {
+            let chip = PiChip::determine().ok_or(MatrixCreationError::ChipDeterminationError)?;
+            log_info!(chip:% = chip; "Detected the Raspberry Pi model {chip}");
+            chip
+        };
+        config.pi_chip = Some(chip);
+
+        // Without access to the physical memory, only the GPIO registers can be mapped.
+        let privileged = OpenOptions::new()
             .read(true)
             .write(true)
-            .open("/dev/mem")
-            .map_err(|_| MatrixCreationError::MemoryAccessError)?;
+            .open(MEM_PATH)
+            .is_ok();
+
+        match (chip, privileged) {
+            (PiChip::BCM2712, true) => Self::create(builder, Rp1RegisterBackend::new),
+            (PiChip::BCM2712, false) => Self::create(builder, Rp1RegisterBackend::new_unprivileged),
+            (_, true) => Self::create(builder, MmapRegisterBackend::new),
+            (_, false) => {
+                if config.hardware_mapping.has_pwm_output_enable() {
+                    return Err(MatrixCreationError::PwmAccessError);
+                }
+                Self::create(builder, MmapRegisterBackend::new_unprivileged)
+            }
+        }
+    }
 
+    fn create<B, F>(
+        builder: RGBMatrixBuilder,
+        create_backend: F,
+    ) -> Result<(Self, Box<Canvas>), MatrixCreationError>
+    where
+        B: RegisterBackend,
+        F: FnOnce(PiChip) -> Result<B, MatrixCreationError> + Send + 'static,
+    {
+        let RGBMatrixBuilder {
+            mut config,
+            requested_inputs,
+            pixel_mappers,
+            queue_depth,
+            blank_on_exit,
+            blank_on_panic,
+        } = builder;
         let chip = if let Some(chip) = config.pi_chip {
             chip
         } else {
-            PiChip::determine().ok_or(MatrixCreationError::ChipDeterminationError)?
+            let chip = PiChip::determine().ok_or(MatrixCreationError::ChipDeterminationError)?;
+            log_info!(chip:% = chip; "Detected the Raspberry Pi model {chip}");
+            chip
         };
 
+        if let Some(core) = config.update_thread_core {
+            if core >= chip.num_cores() {
+                return Err(MatrixCreationError::InvalidCpuCore(core));
+            }
+        }
+
         let max_parallel = config.hardware_mapping.max_parallel_chains();
         if config.parallel > max_parallel {
             return Err(MatrixCreationError::TooManyParallelChains(max_parallel));
@@ -175,32 +515,72 @@ impl RGBMatrix {
                 Self::apply_pixel_mapper(&shared_mapper, &mapper, &config, pixel_designator)?;
         }
 
-        let dither_start_bits = match config.dither_bits {
-            0 => [0, 0, 0, 0],
-            1 => [0, 1, 0, 1],
-            2 => [0, 1, 2, 2],
-            _ => return Err(MatrixCreationError::InvalidDitherBits(config.dither_bits)),
-        };
+        // User provided mappers come last.
+        for mapper in pixel_mappers {
+            let mapper = PixelMapper::Custom(mapper);
+            shared_mapper =
+                Self::apply_pixel_mapper(&shared_mapper, &mapper, &config, pixel_designator)?;
+        }
+
+        let mut dither_start_bits = dither_start_bits(config.dither_bits)
+            .ok_or(MatrixCreationError::InvalidDitherBits(config.dither_bits))?;
 
         // Create two canvases, one for the display update thread and one for the user to modify. They will be
         // swapped out after each frame.
         let canvas = Box::new(Canvas::new(&config, shared_mapper));
         let mut thread_canvas = canvas.clone();
 
-        let (canvas_to_thread_sender, canvas_to_thread_receiver) = sync_channel::<Box<Canvas>>(0);
-        let (canvas_from_thread_sender, canvas_from_thread_receiver) =
-            sync_channel::<Box<Canvas>>(1);
+        let (canvas_to_thread_sender, canvas_to_thread_receiver) =
+            sync_channel::<(Box<Canvas>, usize)>(queue_depth);
+        let (canvas_from_thread_sender, canvas_from_thread_receiver) = channel::<Box<Canvas>>();
+        // Every canvas that is queued is exchanged for a free one. There is one for every place in the
+        // queue, and the update thread frees one whenever it takes a canvas from the queue.
+        for _ in 0..queue_depth {
+            canvas_from_thread_sender
+                .send(canvas.clone())
+                .expect("The receiver exists.");
+        }
         let (shutdown_sender, shutdown_receiver) = channel::<()>();
+        let (reconfiguration_sender, reconfiguration_receiver) = channel::<Reconfiguration>();
+        let refresh_count = Arc::new(AtomicU64::new(0));
+        let thread_refresh_count = Arc::clone(&refresh_count);
+        let watchdog_blanked = Arc::new(AtomicBool::new(false));
+        let thread_watchdog_blanked = Arc::clone(&watchdog_blanked);
         let (input_sender, input_receiver) = channel::<u32>();
+        let update_waker = Arc::new(WakerSlot::default());
+        let thread_update_waker = Arc::clone(&update_waker);
+        let input_waker = Arc::new(WakerSlot::default());
+        let thread_input_waker = Arc::clone(&input_waker);
         let (thread_start_result_sender, thread_start_result_receiver) =
             channel::<Result<u32, MatrixCreationError>>();
 
+        let drop_privileges = config.drop_privileges.clone();
+        let brightness = config.led_brightness.clamp(1, 100);
+        let pwm_bits = config.pwm_bits;
+        let color_correction = config.color_correction.clone();
+        let white_balance = config.white_balance;
+        let color_lookup = canvas.color_lookup();
+
         let thread_handle = spawn(move || {
-            initialize_update_thread(chip);
+            // The preview neither needs a dedicated core nor realtime priority.
+            let mut preview = config.preview.then(TerminalPreview::new);
+            let system_tuning = preview
+                .is_none()
+                .then(|| SystemTuning::apply(chip, &config));
+
+            let backend = match create_backend(chip) {
+                Ok(backend) => backend,
+                Err(error) => {
+                    thread_start_result_sender
+                        .send(Err(error))
+                        .expect("Could not send to main thread.");
+                    return;
+                }
+            };
 
             let mut address_setter = config.row_setter.create(&config);
 
-            let mut gpio = match Gpio::new(chip, &config, address_setter.as_ref()) {
+            let mut gpio = match Gpio::new(chip, &config, address_setter.as_ref(), backend) {
                 Ok(gpio) => gpio,
                 Err(error) => {
                     thread_start_result_sender
@@ -220,61 +600,166 @@ impl RGBMatrix {
             // Dither sequence
             let mut dither_low_bit_sequence = 0;
 
-            let frame_time_target_us = (1_000_000.0 / config.refresh_rate as f64) as u64;
+            let mut frame_time_target_us = frame_time_target_us(config.refresh_rate);
 
             let color_clk_mask = config
                 .hardware_mapping
                 .get_color_clock_mask(config.parallel);
 
             let enabled_input_bits = gpio.request_enabled_inputs(requested_inputs);
+
+            // Capabilities are per thread, so the update thread has to shed its own.
+            if config.drop_privileges == Some(PrivilegeDrop::Capabilities) {
+                if let Err(error) = shed_capabilities() {
+                    thread_start_result_sender
+                        .send(Err(MatrixCreationError::PrivilegeDropError(error)))
+                        .expect("Could not send to main thread.");
+                    return;
+                }
+            }
+
+            let exit_guard = (blank_on_exit || blank_on_panic)
+                .then(|| ExitGuard::new(blank_on_exit, blank_on_panic));
+
+            log_info!(
+                chip:% = chip,
+                rows = config.rows,
+                cols = config.cols,
+                chain_length = config.chain_length,
+                parallel = config.parallel,
+                hardware_mapping:% = config.hardware_mapping,
+                refresh_rate = config.refresh_rate,
+                preview = config.preview;
+                "Matrix started"
+            );
+
             thread_start_result_sender
                 .send(Ok(enabled_input_bits))
                 .expect("Could not send to main thread.");
 
+            // The number of frames the current canvas is still shown before the next one is taken.
+            let mut remaining_cycles: usize = 0;
+
+            // Shown by the watchdog when no canvas was received in time.
+            let blank_canvas = thread_canvas.clone();
+            let watchdog_timeout = config.watchdog_timeout_ms.map(Duration::from_millis);
+            let mut last_canvas_time = Instant::now();
+
             'thread: loop {
                 let start_time = gpio.get_time();
                 loop {
                     // Try to receive a shutdown request.
-                    if shutdown_receiver.try_recv() != Err(TryRecvError::Empty) {
+                    if shutdown_receiver.try_recv() != Err(TryRecvError::Empty)
+                        || exit_guard.as_ref().is_some_and(ExitGuard::exit_requested)
+                    {
                         break 'thread;
                     }
                     // Read input bits and send them if they have changed.
                     let new_inputs = gpio.read();
                     if new_inputs != last_gpio_inputs {
                         match input_sender.send(new_inputs) {
-                            Ok(()) => {}
+                            Ok(()) => thread_input_waker.wake(),
                             Err(_) => {
                                 break 'thread;
                             }
                         }
                         last_gpio_inputs = new_inputs;
                     }
-                    // Wait for a swap canvas.
-                    match canvas_to_thread_receiver.recv_timeout(Duration::from_millis(1)) {
-                        Ok(new_canvas) => {
+                    // Wait for a swap canvas, unless the current one is shown for more frames. A pending
+                    // asynchronous update can only hand over its canvas while waiting.
+                    let received = (remaining_cycles == 0).then(|| {
+                        thread_update_waker.wake();
+                        canvas_to_thread_receiver.recv_timeout(Duration::from_millis(1))
+                    });
+                    // Apply changed settings between frames. This happens after receiving, so changes that
+                    // were made before the canvas was sent apply to it.
+                    while let Ok(reconfiguration) = reconfiguration_receiver.try_recv() {
+                        match reconfiguration {
+                            Reconfiguration::DitherBits(dither_bits) => {
+                                config.dither_bits = dither_bits;
+                                if let Some(start_bits) = self::dither_start_bits(dither_bits) {
+                                    dither_start_bits = start_bits;
+                                }
+                                gpio.set_bitplane_timings(config.pwm_lsb_nanoseconds, dither_bits);
+                            }
+                            Reconfiguration::PwmLsbNanoseconds(nanoseconds) => {
+                                config.pwm_lsb_nanoseconds = nanoseconds;
+                                gpio.set_bitplane_timings(nanoseconds, config.dither_bits);
+                            }
+                            Reconfiguration::RefreshRate(refresh_rate) => {
+                                config.refresh_rate = refresh_rate;
+                                frame_time_target_us = self::frame_time_target_us(refresh_rate);
+                            }
+                            // Not sent, the matrix converts the canvases before they are passed on.
+                            Reconfiguration::Brightness(_)
+                            | Reconfiguration::PwmBits(_)
+                            | Reconfiguration::ColorCorrection(_)
+                            | Reconfiguration::WhiteBalance(_) => {}
+                        }
+                    }
+                    match received {
+                        None => break,
+                        Some(Ok((new_canvas, cycles))) => {
+                            remaining_cycles = cycles;
+                            last_canvas_time = Instant::now();
+                            thread_watchdog_blanked.store(false, Ordering::Relaxed);
+                            if let Some(preview) = &mut preview {
+                                preview.canvas_changed();
+                            }
                             let old_canvas = replace(&mut thread_canvas, new_canvas);
                             match canvas_from_thread_sender.send(old_canvas) {
-                                Ok(()) => break,
+                                Ok(()) => {
+                                    thread_update_waker.wake();
+                                    break;
+                                }
                                 Err(_) => {
                                     break 'thread;
                                 }
                             };
                         }
-                        Err(RecvTimeoutError::Disconnected) => {
+                        Some(Err(RecvTimeoutError::Disconnected)) => {
                             break 'thread;
                         }
-                        Err(RecvTimeoutError::Timeout) => {}
+                        Some(Err(RecvTimeoutError::Timeout)) => {
+                            // Blank the panels once if the canvases stopped coming, e.g. because the
+                            // program hangs.
+                            let expired = watchdog_timeout
+                                .is_some_and(|timeout| last_canvas_time.elapsed() > timeout);
+                            if expired && !thread_watchdog_blanked.swap(true, Ordering::Relaxed) {
+                                log_warn!(
+                                    "No canvas was passed within the watchdog timeout. Blanking the \
+                                    panels."
+                                );
+                                if let Some(preview) = &mut preview {
+                                    preview.show(&blank_canvas);
+                                } else {
+                                    blank_canvas.dump_to_matrix(
+                                        &mut gpio,
+                                        &config.hardware_mapping,
+                                        address_setter.as_mut(),
+                                        0,
+                                        color_clk_mask,
+                                    );
+                                }
+                            }
+                        }
                     }
                 }
 
-                thread_canvas.dump_to_matrix(
-                    &mut gpio,
-                    &config.hardware_mapping,
-                    address_setter.as_mut(),
-                    dither_start_bits[dither_low_bit_sequence % dither_start_bits.len()],
-                    color_clk_mask,
-                );
+                if let Some(preview) = &mut preview {
+                    preview.update(&thread_canvas);
+                } else {
+                    thread_canvas.dump_to_matrix(
+                        &mut gpio,
+                        &config.hardware_mapping,
+                        address_setter.as_mut(),
+                        dither_start_bits[dither_low_bit_sequence % dither_start_bits.len()],
+                        color_clk_mask,
+                    );
+                }
                 dither_low_bit_sequence += 1;
+                remaining_cycles -= 1;
+                thread_refresh_count.fetch_add(1, Ordering::Relaxed);
 
                 // Sleep for the rest of the frame.
                 let now_time = gpio.get_time();
@@ -285,14 +770,29 @@ impl RGBMatrix {
             }
 
             // Turn it off.
-            thread_canvas.fill(0, 0, 0);
-            thread_canvas.dump_to_matrix(
-                &mut gpio,
-                &config.hardware_mapping,
-                address_setter.as_mut(),
-                0,
-                color_clk_mask,
-            );
+            if preview.is_none() {
+                thread_canvas.fill(0, 0, 0);
+                thread_canvas.dump_to_matrix(
+                    &mut gpio,
+                    &config.hardware_mapping,
+                    address_setter.as_mut(),
+                    0,
+                    color_clk_mask,
+                );
+                // The output enable is active low.
+                gpio.wait_pulse_finished();
+                gpio.set_bits(config.hardware_mapping.output_enable);
+            }
+
+            // Let pending futures and input streams see that the channels are closed.
+            drop(canvas_from_thread_sender);
+            drop(input_sender);
+            thread_update_waker.wake();
+            thread_input_waker.wake();
+
+            // The panels are blank, so a signal that stopped the thread can end the process now.
+            drop(system_tuning);
+            drop(exit_guard);
         });
 
         let enabled_input_bits = thread_start_result_receiver
@@ -301,14 +801,37 @@ impl RGBMatrix {
 
         let rgbmatrix = Self {
             thread_handle: Some(thread_handle),
-            input_receiver,
+            input_receiver: Arc::new(Mutex::new(input_receiver)),
+            input_waker,
             shutdown_sender,
+            reconfiguration_sender,
+            reconfiguration_generation: 0,
+            brightness,
+            pwm_bits,
+            color_correction,
+            white_balance,
+            color_lookup,
             canvas_to_thread_sender,
             canvas_from_thread_receiver,
+            update_waker,
             enabled_input_bits,
+            blank_canvas: canvas.clone(),
+            spare_canvases: Vec::new(),
+            owed_canvases: 0,
             frame_rate_monitor: FrameRateMonitor::new(),
+            refresh_count,
+            watchdog_blanked,
+            thread_error: None,
         };
 
+        // The hardware is initialized, so root privileges are no longer needed. On failure, the matrix is
+        // dropped and the update thread shut down.
+        if let Some(privilege_drop) = &drop_privileges {
+            privilege_drop
+                .apply()
+                .map_err(MatrixCreationError::PrivilegeDropError)?;
+        }
+
         Ok((rgbmatrix, canvas))
     }
 
@@ -327,9 +850,15 @@ impl RGBMatrix {
             for x in 0..new_width {
                 let [orig_x, orig_y] = mapper.map_visible_to_matrix(old_width, old_height, x, y);
                 if orig_x >= old_width || orig_y >= old_height {
-                    return Err(MatrixCreationError::PixelMapperError(
-                        "Invalid dimensions detected. This is likely a bug.".to_string(),
-                    ));
+                    let message = match mapper {
+                        PixelMapper::Custom(mapper) => format!(
+                            "The pixel mapper {} maps the visible pixel ({x}, {y}) to ({orig_x}, \
+                            {orig_y}), which is outside of the {old_width}x{old_height} matrix.",
+                            mapper.name()
+                        ),
+                        _ => "Invalid dimensions detected. This is likely a bug.".to_string(),
+                    };
+                    return Err(MatrixCreationError::PixelMapperError(message));
                 }
                 let orig_designator = shared_mapper.get(orig_x, orig_y).unwrap();
                 *new_mapper.get_mut(x, y).unwrap() = *orig_designator;
@@ -338,24 +867,216 @@ impl RGBMatrix {
         Ok(new_mapper)
     }
 
-    /// Updates the matrix with the new canvas. Blocks until the end of the current frame.
-    pub fn update_on_vsync(&mut self, canvas: Box<Canvas>) -> Box<Canvas> {
-        let Self {
-            canvas_to_thread_sender,
-            canvas_from_thread_receiver,
-            frame_rate_monitor,
-            ..
-        } = self;
+    /// Updates the matrix with the new canvas and returns a canvas that is no longer shown. Blocks until the
+    /// end of the current frame, or with a queue, see [`RGBMatrixBuilder::queue_depth`], until there is a
+    /// place in the queue. Fails if the update thread stopped.
+    pub fn update_on_vsync(&mut self, canvas: Box<Canvas>) -> Result<Box<Canvas>, UpdateError> {
+        self.update_on_vsync_multiple(canvas, 1)
+    }
+
+    /// Like [`RGBMatrix::update_on_vsync`], but the canvas is shown for exactly `frames` refresh cycles
+    /// before the next canvas is taken, at least one. This paces the caller at a fixed fraction of the
+    /// refresh rate, e.g. 30 frames per second with a refresh rate of 120 Hz and `frames` set to 4.
+    pub fn update_on_vsync_multiple(
+        &mut self,
+        mut canvas: Box<Canvas>,
+        frames: usize,
+    ) -> Result<Box<Canvas>, UpdateError> {
+        self.receive_owed_canvases(true)?;
+        self.apply_reconfiguration(&mut canvas);
+        if self
+            .canvas_to_thread_sender
+            .send((canvas, frames.max(1)))
+            .is_err()
+        {
+            return Err(self.thread_stopped());
+        }
+
+        self.frame_rate_monitor.update();
+
+        let mut canvas = self
+            .canvas_from_thread_receiver
+            .recv()
+            .map_err(|_| self.thread_stopped())?;
+        self.apply_reconfiguration(&mut canvas);
+        Ok(canvas)
+    }
+
+    /// Like [`RGBMatrix::update_on_vsync`], but returns a future instead of blocking. The future is woken by
+    /// the update thread and does not depend on a particular async runtime.
+    ///
+    /// With the default [`RGBMatrixBuilder::queue_depth`] of 0, the canvas can only be handed over while the
+    /// update thread waits for it at the end of a frame, which it does for about a millisecond after waking
+    /// the future. If the future is polled later, the canvas is taken at the end of the next frame. Use a
+    /// queue depth of at least 1 if the executor may not poll the future that quickly.
+    ///
+    /// Dropping the future before it resolved cancels the update. A canvas that was not handed over yet is
+    /// kept for [`RGBMatrix::create_offscreen_canvas`]. If the update thread already took it, the canvas it
+    /// returns for it is received before the next update, so the next update still waits for its frame.
+    pub fn update_on_vsync_async(&mut self, canvas: Box<Canvas>) -> UpdateFuture<'_> {
+        UpdateFuture::new(self, canvas, 1)
+    }
 
-        canvas_to_thread_sender
-            .send(canvas)
-            .expect("Display update thread shut down unexpectedly.");
+    /// Like [`RGBMatrix::update_on_vsync`], but without blocking. If the update thread can not take the
+    /// canvas right now, it is returned with [`TryUpdateError::Full`].
+    pub fn try_update(&mut self, mut canvas: Box<Canvas>) -> Result<Box<Canvas>, TryUpdateError> {
+        match self.receive_owed_canvases(false) {
+            Ok(true) => {}
+            Ok(false) => return Err(TryUpdateError::Full(canvas)),
+            Err(error) => return Err(TryUpdateError::Stopped(error)),
+        }
+        self.apply_reconfiguration(&mut canvas);
+        match self.canvas_to_thread_sender.try_send((canvas, 1)) {
+            Ok(()) => {}
+            Err(TrySendError::Full((canvas, _))) => return Err(TryUpdateError::Full(canvas)),
+            Err(TrySendError::Disconnected(_)) => {
+                return Err(TryUpdateError::Stopped(self.thread_stopped()))
+            }
+        }
 
-        frame_rate_monitor.update();
+        self.frame_rate_monitor.update();
 
-        canvas_from_thread_receiver
+        // A free canvas is available or about to be sent by the update thread.
+        let mut canvas = self
+            .canvas_from_thread_receiver
             .recv()
-            .expect("Display update thread shut down unexpectedly.")
+            .map_err(|_| TryUpdateError::Stopped(self.thread_stopped()))?;
+        self.apply_reconfiguration(&mut canvas);
+        Ok(canvas)
+    }
+
+    /// Receive the canvases the update thread returns for cancelled asynchronous updates, so the next canvas
+    /// that is received belongs to the next update. Without `wait`, returns whether all of them were
+    /// received.
+    pub(crate) fn receive_owed_canvases(&mut self, wait: bool) -> Result<bool, UpdateError> {
+        while self.owed_canvases > 0 {
+            let received = if wait {
+                self.canvas_from_thread_receiver
+                    .recv()
+                    .map_err(|_| TryRecvError::Disconnected)
+            } else {
+                self.canvas_from_thread_receiver.try_recv()
+            };
+            match received {
+                Ok(canvas) => {
+                    self.owed_canvases -= 1;
+                    self.spare_canvases.push(*canvas);
+                }
+                Err(TryRecvError::Empty) => return Ok(false),
+                Err(TryRecvError::Disconnected) => return Err(self.thread_stopped()),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Join the update thread after one of its channels was closed and return why it stopped.
+    pub(crate) fn thread_stopped(&mut self) -> UpdateError {
+        if let Some(handle) = self.thread_handle.take() {
+            self.thread_error = Some(match handle.join() {
+                Ok(()) => UpdateError::ThreadExited,
+                Err(payload) => UpdateError::ThreadPanicked(panic_message(payload.as_ref())),
+            });
+        }
+        self.thread_error
+            .clone()
+            .unwrap_or(UpdateError::ThreadExited)
+    }
+
+    /// The state of the update thread, including whether its watchdog blanked the panels. If it stopped,
+    /// it is joined to determine why.
+    pub fn thread_state(&mut self) -> ThreadState {
+        let finished = self
+            .thread_handle
+            .as_ref()
+            .is_none_or(JoinHandle::is_finished);
+        if finished {
+            ThreadState::Stopped(self.thread_stopped())
+        } else if self.watchdog_blanked.load(Ordering::Relaxed) {
+            ThreadState::WatchdogBlanked
+        } else {
+            ThreadState::Running
+        }
+    }
+
+    /// Create an additional blank canvas, e.g. to render frames ahead of time. It can be passed to
+    /// [`RGBMatrix::update_on_vsync`] like the canvas that was returned on creation. The canvases of
+    /// cancelled asynchronous updates are cleared and reused first.
+    #[must_use]
+    pub fn create_offscreen_canvas(&mut self) -> Box<Canvas> {
+        let mut canvas = match self.spare_canvases.pop() {
+            Some(mut canvas) => {
+                canvas.reset_to(&self.blank_canvas);
+                Box::new(canvas)
+            }
+            None => self.blank_canvas.clone(),
+        };
+        self.apply_reconfiguration(&mut canvas);
+        canvas
+    }
+
+    /// Change a setting of the update thread without recreating the matrix. The change is applied between
+    /// two frames.
+    ///
+    /// The brightness, PWM bits, color correction and white balance are applied to the canvases: canvases
+    /// that were drawn before the change are converted once on the calling thread, when they are passed to
+    /// [`RGBMatrix::update_on_vsync`] or returned from it, so the change is shown with the next canvas.
+    /// Changes made with [`Canvas::set_brightness`] or [`Canvas::set_pwm_bits`] after that are kept. The
+    /// color lookup for a new color correction or white balance is computed here and shared by all
+    /// canvases.
+    pub fn reconfigure(
+        &mut self,
+        reconfiguration: Reconfiguration,
+    ) -> Result<(), ReconfigurationError> {
+        match reconfiguration {
+            Reconfiguration::Brightness(brightness) => {
+                self.brightness = brightness.clamp(1, 100);
+            }
+            Reconfiguration::PwmBits(pwm_bits) if !(1..=K_BIT_PLANES).contains(&pwm_bits) => {
+                return Err(ReconfigurationError::InvalidPwmBits(pwm_bits));
+            }
+            Reconfiguration::PwmBits(pwm_bits) => {
+                self.pwm_bits = pwm_bits;
+            }
+            Reconfiguration::ColorCorrection(correction) => {
+                self.color_correction = correction;
+                self.color_lookup =
+                    Arc::new(ColorLookup::new(&self.color_correction, self.white_balance));
+            }
+            Reconfiguration::WhiteBalance(white_balance) => {
+                self.white_balance = white_balance;
+                self.color_lookup =
+                    Arc::new(ColorLookup::new(&self.color_correction, self.white_balance));
+            }
+            Reconfiguration::DitherBits(dither_bits)
+                if dither_start_bits(dither_bits).is_none() =>
+            {
+                return Err(ReconfigurationError::InvalidDitherBits(dither_bits));
+            }
+            Reconfiguration::RefreshRate(0) => {
+                return Err(ReconfigurationError::InvalidRefreshRate(0));
+            }
+            other => {
+                return self
+                    .reconfiguration_sender
+                    .send(other)
+                    .map_err(|_| ReconfigurationError::Stopped(self.thread_stopped()));
+            }
+        }
+        self.reconfiguration_generation += 1;
+        match self.thread_state() {
+            ThreadState::Stopped(error) => Err(ReconfigurationError::Stopped(error)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Convert a canvas that was drawn before the latest reconfiguration of the canvas settings.
+    pub(crate) fn apply_reconfiguration(&self, canvas: &mut Canvas) {
+        canvas.apply_reconfiguration(
+            self.reconfiguration_generation,
+            self.brightness,
+            self.pwm_bits,
+            &self.color_lookup,
+        );
     }
 
     /// Get the bits that were available for input.
@@ -366,7 +1087,27 @@ impl RGBMatrix {
 
     /// Tries to receive a new GPIO input as specified with [`RGBMatrix::request_enabled_inputs`].
     pub fn receive_new_inputs(&mut self, timeout: Duration) -> Option<u32> {
-        self.input_receiver.recv_timeout(timeout).ok()
+        self.input_receiver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .recv_timeout(timeout)
+            .ok()
+    }
+
+    /// Receive the GPIO inputs asynchronously, see [`InputStream`]. The stream shares the inputs with
+    /// [`RGBMatrix::receive_new_inputs`] and with other streams, so every change is received only once.
+    #[must_use]
+    pub fn input_stream(&self) -> InputStream {
+        InputStream::new(
+            Arc::clone(&self.input_receiver),
+            Arc::clone(&self.input_waker),
+        )
+    }
+
+    /// The number of refresh cycles the update thread has shown since the matrix was created.
+    #[must_use]
+    pub fn refresh_count(&self) -> u64 {
+        self.refresh_count.load(Ordering::Relaxed)
     }
 
     /// Get the average frame rate over the last 60 frames.
@@ -389,3 +1130,87 @@ impl Drop for RGBMatrix {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        simulated_backend::test_support::{simulated_matrix, test_config},
+        RGBMatrixBuilder, RGBMatrixConfig, SimulatedRegisterBackend, ThreadState, TryUpdateError,
+    };
+
+    #[test]
+    fn test_frame_queue() {
+        let (mut matrix, canvas) = RGBMatrixBuilder::new(test_config())
+            .queue_depth(2)
+            .build_with_backend(|_| Ok(SimulatedRegisterBackend::new()))
+            .unwrap();
+        let offscreen = matrix.create_offscreen_canvas();
+        assert_eq!(
+            (offscreen.width(), offscreen.height()),
+            (canvas.width(), canvas.height())
+        );
+
+        // The queue takes two canvases without waiting for the update thread.
+        let mut canvases = vec![canvas, offscreen];
+        let mut free = Vec::new();
+        for canvas in canvases.drain(..) {
+            free.push(matrix.try_update(canvas).unwrap());
+        }
+
+        // Keep submitting until the update thread has shown enough frames.
+        let start = Instant::now();
+        let mut shown = 0;
+        while shown < 10 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            let canvas = free.remove(0);
+            match matrix.try_update(canvas) {
+                Ok(canvas) => {
+                    shown += 1;
+                    free.push(canvas);
+                }
+                Err(TryUpdateError::Full(canvas)) => {
+                    free.push(canvas);
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(TryUpdateError::Stopped(error)) => panic!("{error}"),
+            }
+        }
+        assert_eq!(free.len(), 2);
+        let canvas = free.pop().unwrap();
+        let _canvas = matrix.update_on_vsync(canvas).unwrap();
+    }
+
+    #[test]
+    fn test_update_on_vsync_multiple() {
+        let (mut matrix, mut canvas) = simulated_matrix(test_config());
+        assert_eq!(matrix.refresh_count(), 0);
+
+        // Each canvas is only replaced after it has been shown three times.
+        for _ in 0..4 {
+            canvas = matrix.update_on_vsync_multiple(canvas, 3).unwrap();
+        }
+        assert!((9..=12).contains(&matrix.refresh_count()));
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let (mut matrix, mut canvas) = simulated_matrix(RGBMatrixConfig {
+            watchdog_timeout_ms: Some(20),
+            ..test_config()
+        });
+        canvas = matrix.update_on_vsync(canvas).unwrap();
+        assert_eq!(matrix.thread_state(), ThreadState::Running);
+
+        let start = Instant::now();
+        while matrix.thread_state() != ThreadState::WatchdogBlanked {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        // The next canvas is shown again.
+        let _canvas = matrix.update_on_vsync(canvas).unwrap();
+        assert_eq!(matrix.thread_state(), ThreadState::Running);
+    }
+}
@@ -1,6 +1,10 @@
-use std::{error::Error, str::FromStr};
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    str::FromStr,
+};
 
-use crate::{gpio::Gpio, RGBMatrixConfig};
+use crate::{gpio::Gpio, register_backend::RegisterBackend, RGBMatrixConfig};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RowAddressSetterType {
@@ -26,8 +30,18 @@ impl FromStr for RowAddressSetterType {
     }
 }
 
+impl Display for RowAddressSetterType {
+    /// The name as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 impl RowAddressSetterType {
-    pub(crate) fn create(self, config: &RGBMatrixConfig) -> Box<dyn RowAddressSetter> {
+    pub(crate) fn create<B: RegisterBackend>(
+        self,
+        config: &RGBMatrixConfig,
+    ) -> Box<dyn RowAddressSetter<B>> {
         match self {
             RowAddressSetterType::Direct => Box::new(DirectRowAddressSetter::new(config)),
             RowAddressSetterType::ShiftRegister => {
@@ -45,9 +59,9 @@ impl RowAddressSetterType {
 }
 
 /// Different panel types use different techniques to set the row address.
-pub(crate) trait RowAddressSetter {
+pub(crate) trait RowAddressSetter<B: RegisterBackend> {
     fn used_bits(&self) -> u32;
-    fn set_row_address(&mut self, gpio: &mut Gpio, row: usize);
+    fn set_row_address(&mut self, gpio: &mut Gpio<B>, row: usize);
 }
 
 pub(crate) struct DirectRowAddressSetter {
@@ -90,12 +104,12 @@ impl DirectRowAddressSetter {
     }
 }
 
-impl RowAddressSetter for DirectRowAddressSetter {
+impl<B: RegisterBackend> RowAddressSetter<B> for DirectRowAddressSetter {
     fn used_bits(&self) -> u32 {
         self.row_mask
     }
 
-    fn set_row_address(&mut self, gpio: &mut Gpio, row: usize) {
+    fn set_row_address(&mut self, gpio: &mut Gpio<B>, row: usize) {
         if self.last_row == Some(row) {
             return;
         }
@@ -152,12 +166,12 @@ impl SM5266RowAddressSetter {
     }
 }
 
-impl RowAddressSetter for SM5266RowAddressSetter {
+impl<B: RegisterBackend> RowAddressSetter<B> for SM5266RowAddressSetter {
     fn used_bits(&self) -> u32 {
         self.row_mask
     }
 
-    fn set_row_address(&mut self, gpio: &mut Gpio, row: usize) {
+    fn set_row_address(&mut self, gpio: &mut Gpio<B>, row: usize) {
         if self.last_row == Some(row) {
             return;
         }
@@ -203,12 +217,12 @@ impl ShiftRegisterRowAddressSetter {
     }
 }
 
-impl RowAddressSetter for ShiftRegisterRowAddressSetter {
+impl<B: RegisterBackend> RowAddressSetter<B> for ShiftRegisterRowAddressSetter {
     fn used_bits(&self) -> u32 {
         self.row_mask
     }
 
-    fn set_row_address(&mut self, gpio: &mut Gpio, row: usize) {
+    fn set_row_address(&mut self, gpio: &mut Gpio<B>, row: usize) {
         if self.last_row == Some(row) {
             return;
         }
@@ -252,12 +266,12 @@ impl ABCShiftRegisterRowAddressSetter {
     }
 }
 
-impl RowAddressSetter for ABCShiftRegisterRowAddressSetter {
+impl<B: RegisterBackend> RowAddressSetter<B> for ABCShiftRegisterRowAddressSetter {
     fn used_bits(&self) -> u32 {
         self.row_mask
     }
 
-    fn set_row_address(&mut self, gpio: &mut Gpio, row: usize) {
+    fn set_row_address(&mut self, gpio: &mut Gpio<B>, row: usize) {
         if self.last_row == Some(row) {
             return;
         }
@@ -309,12 +323,12 @@ impl DirectABCDLineRowAddressSetter {
     }
 }
 
-impl RowAddressSetter for DirectABCDLineRowAddressSetter {
+impl<B: RegisterBackend> RowAddressSetter<B> for DirectABCDLineRowAddressSetter {
     fn used_bits(&self) -> u32 {
         self.row_mask
     }
 
-    fn set_row_address(&mut self, gpio: &mut Gpio, row: usize) {
+    fn set_row_address(&mut self, gpio: &mut Gpio<B>, row: usize) {
         if self.last_row == Some(row) {
             return;
         }
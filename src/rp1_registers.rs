@@ -0,0 +1,191 @@
+use std::rc::Rc;
+
+use memmap2::MmapMut;
+
+use crate::{
+    chip::PiChip,
+    register_backend::RegisterBackend,
+    registers::{mmap_bcm_register, mmap_register, GPIOFunction, MmapPtr, MonotonicTimer, Timer},
+    rgb_matrix::MatrixCreationError,
+};
+
+// See the RP1 peripherals datasheet, https://datasheets.raspberrypi.com/rp1/rp1-peripherals.pdf
+
+/// Number of pins in bank 0, which holds the pins on the 40 pin header.
+const BANK0_PINS: usize = 28;
+
+/// Maps IO bank 0, the RIO block and the pads of bank 0 for the members of the `gpio` group.
+const GPIOMEM_PATH: &str = "/dev/gpiomem0";
+
+// IO bank 0, the RIO block and the pads are adjacent. We map them all at once.
+const IO_BANK0_OFFSET: u64 = 0x000D_0000;
+const GPIO_SIZE_BYTES: usize = 0x0003_0000;
+
+// IO bank 0: one status and one control register per pin.
+const IO_BANK0: usize = 0x0000_0000;
+const IO_CTRL: usize = 0x04;
+const IO_CTRL_FUNCSEL_MASK: u32 = 0x1F;
+/// Function that connects the pin to the registered IO (RIO) block.
+const FUNCSEL_SYS_RIO: u32 = 5;
+
+// Registered IO block.
+const RIO: usize = 0x0001_0000;
+const RIO_OUT: usize = 0x00;
+const RIO_OE: usize = 0x04;
+const RIO_SYNC_IN: usize = 0x08;
+/// Writes to this alias of a register set the bits that are 1.
+const RIO_SET_ALIAS: usize = 0x2000;
+/// Writes to this alias of a register clear the bits that are 1.
+const RIO_CLR_ALIAS: usize = 0x3000;
+
+// Pads of bank 0. The first register selects the voltage, the pads follow.
+const PADS_BANK0: usize = 0x0002_0000;
+const PADS_GPIO0: usize = 0x04;
+/// Output disable
+const PADS_OD: u32 = 1 << 7;
+/// Input enable
+const PADS_IE: u32 = 1 << 6;
+
+struct Rp1GpioRegisters {
+    ctrl: Vec<MmapPtr<u32>>,
+    pads: Vec<MmapPtr<u32>>,
+    out_set: MmapPtr<u32>,
+    out_clr: MmapPtr<u32>,
+    oe_set: MmapPtr<u32>,
+    oe_clr: MmapPtr<u32>,
+    sync_in: MmapPtr<u32>,
+}
+
+impl Rp1GpioRegisters {
+    fn new(map: Rc<MmapMut>) -> Self {
+        let ctrl = (0..BANK0_PINS)
+            .map(|pin| MmapPtr::new(map.clone(), IO_BANK0 + 8 * pin + IO_CTRL))
+            .collect();
+        let pads = (0..BANK0_PINS)
+            .map(|pin| MmapPtr::new(map.clone(), PADS_BANK0 + PADS_GPIO0 + 4 * pin))
+            .collect();
+        Self {
+            ctrl,
+            pads,
+            out_set: MmapPtr::new(map.clone(), RIO + RIO_SET_ALIAS + RIO_OUT),
+            out_clr: MmapPtr::new(map.clone(), RIO + RIO_CLR_ALIAS + RIO_OUT),
+            oe_set: MmapPtr::new(map.clone(), RIO + RIO_SET_ALIAS + RIO_OE),
+            oe_clr: MmapPtr::new(map.clone(), RIO + RIO_CLR_ALIAS + RIO_OE),
+            sync_in: MmapPtr::new(map, RIO + RIO_SYNC_IN),
+        }
+    }
+
+    /// Inputs and outputs are connected to the RIO block. The alternate functions are passed on to the
+    /// function select of the RP1 as they are, they do not match the ones of the BCM chips.
+    fn set_function(&mut self, pin: u8, function: GPIOFunction) {
+        let pin_index = usize::from(pin);
+        if pin_index >= BANK0_PINS {
+            return;
+        }
+        let funcsel = match function {
+            GPIOFunction::Input | GPIOFunction::Output => FUNCSEL_SYS_RIO,
+            GPIOFunction::Alt0 => 0,
+            GPIOFunction::Alt1 => 1,
+            GPIOFunction::Alt2 => 2,
+            GPIOFunction::Alt3 => 3,
+            GPIOFunction::Alt4 => 4,
+            GPIOFunction::Alt5 => 5,
+        };
+        let bit = 1 << pin;
+        if function == GPIOFunction::Output {
+            self.oe_set.write(bit);
+        } else {
+            self.oe_clr.write(bit);
+        }
+        let pad = &self.pads[pin_index];
+        pad.write((pad.read() & !PADS_OD) | PADS_IE);
+        let ctrl = &self.ctrl[pin_index];
+        ctrl.write((ctrl.read() & !IO_CTRL_FUNCSEL_MASK) | funcsel);
+    }
+}
+
+/// The register backend of the Raspberry Pi 5. The GPIO pins are driven through the registered IO block
+/// of the RP1 and time is measured with `clock_gettime`, as the RP1 has no system timer. Hardware PWM is
+/// not supported, so the output enable is always pulsed by software.
+pub struct Rp1RegisterBackend {
+    gpio_registers: Rp1GpioRegisters,
+    timer: MonotonicTimer,
+}
+
+impl Rp1RegisterBackend {
+    /// Map the GPIO registers of the RP1 from `/dev/mem`.
+    pub fn new(chip: PiChip) -> Result<Self, MatrixCreationError> {
+        let map = mmap_bcm_register(chip, IO_BANK0_OFFSET, GPIO_SIZE_BYTES)?;
+        Ok(Self::from_map(map))
+    }
+
+    /// Map the GPIO registers of the RP1 from `/dev/gpiomem0`, which is possible for the members of the
+    /// `gpio` group.
+    pub fn new_unprivileged(_chip: PiChip) -> Result<Self, MatrixCreationError> {
+        let map = mmap_register(GPIOMEM_PATH, 0, GPIO_SIZE_BYTES)?;
+        Ok(Self::from_map(map))
+    }
+
+    fn from_map(map: Rc<MmapMut>) -> Self {
+        Self {
+            gpio_registers: Rp1GpioRegisters::new(map),
+            timer: MonotonicTimer,
+        }
+    }
+}
+
+impl RegisterBackend for Rp1RegisterBackend {
+    fn select_function(&mut self, pin: u8, function: GPIOFunction) {
+        self.gpio_registers.set_function(pin, function);
+    }
+
+    #[inline]
+    fn write_set_bits(&mut self, value: u32) {
+        self.gpio_registers.out_set.write(value);
+    }
+
+    #[inline]
+    fn write_clr_bits(&mut self, value: u32) {
+        self.gpio_registers.out_clr.write(value);
+    }
+
+    #[inline]
+    fn read_pin_level0(&self) -> u32 {
+        self.gpio_registers.sync_in.read()
+    }
+
+    #[inline]
+    fn get_time(&self) -> u64 {
+        self.timer.get_time()
+    }
+
+    fn sleep(&mut self, duration_us: u64) {
+        self.timer.sleep(duration_us);
+    }
+
+    fn sleep_at_most(&mut self, duration_us: u64) {
+        self.timer.sleep_at_most(duration_us);
+    }
+
+    fn sleep_nanos(&mut self, duration_ns: u64) {
+        self.timer.sleep_nanos(duration_ns);
+    }
+
+    fn supports_hardware_pwm(&self) -> bool {
+        false
+    }
+
+    // The PWM block is never used, see `supports_hardware_pwm`.
+
+    fn set_pwm_ctl(&mut self, _value: u32) {}
+
+    fn set_pwm_pulse_period(&mut self, _value: u32) {}
+
+    fn push_pwm_fifo(&mut self, _value: u32) {}
+
+    fn pwm_fifo_empty(&self) -> bool {
+        true
+    }
+
+    fn init_pwm_divider(&mut self, _divider: u32) {}
+}
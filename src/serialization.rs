@@ -0,0 +1,130 @@
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    canvas::LedSequence,
+    chip::PiChip,
+    color::{ColorCorrection, WhiteBalance},
+    dithering::SpatialDithering,
+    hardware_mapping::HardwareMapping,
+    init_sequence::PanelType,
+    multiplex_mapper::MultiplexMapperType,
+    named_pixel_mapper::NamedPixelMapperType,
+    privileges::PrivilegeDrop,
+    row_address_setter::RowAddressSetterType,
+    system_tuning::ThreadScheduling,
+};
+
+/// (De)serialize the types as the strings that are accepted on the command line, so a configuration file
+/// uses the same values as the flags.
+macro_rules! serde_as_str {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl Serialize for $type {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $type {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    String::deserialize(deserializer)?
+                        .parse()
+                        .map_err(D::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+serde_as_str!(
+    ColorCorrection,
+    HardwareMapping,
+    LedSequence,
+    MultiplexMapperType,
+    NamedPixelMapperType,
+    PanelType,
+    PiChip,
+    PrivilegeDrop,
+    RowAddressSetterType,
+    SpatialDithering,
+    ThreadScheduling,
+    WhiteBalance,
+);
+
+#[cfg(test)]
+mod tests {
+    use serde::{de::value::StrDeserializer, Deserialize};
+
+    use crate::{
+        ColorCorrection, HardwareMapping, LedSequence, NamedPixelMapperType, PiChip, PrivilegeDrop,
+        RGBMatrixConfig, RowAddressSetterType, SpatialDithering, ThreadScheduling, WhiteBalance,
+    };
+
+    #[test]
+    fn test_round_trip() {
+        let mappers = [
+            NamedPixelMapperType::Mirror(true),
+            NamedPixelMapperType::Rotate(270),
+            NamedPixelMapperType::UMapper,
+            NamedPixelMapperType::Layout("/etc/wall.layout".into()),
+        ];
+        for mapper in mappers {
+            let text = mapper.to_string();
+            let deserializer = StrDeserializer::<serde::de::value::Error>::new(&text);
+            assert_eq!(NamedPixelMapperType::deserialize(deserializer), Ok(mapper));
+        }
+        let deserializer = StrDeserializer::<serde::de::value::Error>::new("AdafruitHatPwm");
+        assert_eq!(
+            HardwareMapping::deserialize(deserializer),
+            Ok(HardwareMapping::adafruit_hat_pwm())
+        );
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::adafruit_hat(),
+            rows: 32,
+            pi_chip: Some(PiChip::BCM2711),
+            slowdown: Some(2),
+            pixelmapper: vec![
+                NamedPixelMapperType::Rotate(90),
+                NamedPixelMapperType::Mirror(false),
+            ],
+            row_setter: RowAddressSetterType::ShiftRegister,
+            led_sequence: LedSequence::Bgr,
+            color_correction: ColorCorrection::Gamma(2.2),
+            white_balance: WhiteBalance {
+                red: 1.0,
+                green: 0.75,
+                blue: 0.5,
+            },
+            spatial_dithering: SpatialDithering::Bayer,
+            update_thread_core: Some(2),
+            update_thread_scheduling: ThreadScheduling::Fifo(50),
+            drop_privileges: Some(PrivilegeDrop::Capabilities),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            serde_json::from_str::<RGBMatrixConfig>(&json).unwrap(),
+            config
+        );
+
+        // Missing fields take their default value.
+        let config: RGBMatrixConfig = serde_json::from_str(
+            r#"{ "hardware_mapping": "Regular", "rows": 16, "pixelmapper": ["U-mapper"] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            RGBMatrixConfig {
+                hardware_mapping: HardwareMapping::regular(),
+                rows: 16,
+                pixelmapper: vec![NamedPixelMapperType::UMapper],
+                ..Default::default()
+            }
+        );
+        assert!(serde_json::from_str::<RGBMatrixConfig>(r#"{ "pi_chip": "BCM1234" }"#).is_err());
+    }
+}
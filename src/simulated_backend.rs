@@ -0,0 +1,254 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    register_backend::RegisterBackend,
+    registers::{GPIOFunction, PWM_CTL_CLRF1, PWM_CTL_PWEN1},
+};
+
+/// Number of GPIO pins that have a function select entry.
+const NUM_PINS: usize = 54;
+/// Time a single write to the GPIO set or clear register takes by default.
+const DEFAULT_GPIO_WRITE_NS: u64 = 10;
+/// The PWM clock is derived from the 500 MHz PLLD.
+const PLLD_PERIOD_NS: u64 = 2;
+
+struct SimulatedState {
+    time_ns: u64,
+    gpio_write_ns: u64,
+    functions: [GPIOFunction; NUM_PINS],
+    output_levels: u32,
+    input_levels: u32,
+    pwm_ctl: u32,
+    pwm_range: u32,
+    pwm_fifo: VecDeque<u32>,
+    pwm_divider: u32,
+    pwm_busy_until_ns: u64,
+}
+
+impl SimulatedState {
+    fn output_mask(&self) -> u32 {
+        self.functions
+            .iter()
+            .take(u32::BITS as usize)
+            .enumerate()
+            .filter(|(_, function)| **function == GPIOFunction::Output)
+            .fold(0, |mask, (pin, _)| mask | (1 << pin))
+    }
+}
+
+/// A register backend that keeps all registers in memory and runs on a virtual clock. It needs no
+/// privileges and no Raspberry Pi, so it can be used to drive an [`RGBMatrix`](crate::RGBMatrix) on any
+/// machine, e.g. in tests.
+///
+/// Every GPIO write advances the virtual clock by a fixed amount and sleeping advances it by the requested
+/// duration without actually sleeping. The backend can be cloned; all clones share the same registers, so
+/// a clone that is kept by the caller can be used to observe the backend that was handed to the matrix.
+#[derive(Clone)]
+pub struct SimulatedRegisterBackend {
+    state: Arc<Mutex<SimulatedState>>,
+}
+
+impl Default for SimulatedRegisterBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedRegisterBackend {
+    #[must_use]
+    pub fn new() -> Self {
+        let state = SimulatedState {
+            time_ns: 0,
+            gpio_write_ns: DEFAULT_GPIO_WRITE_NS,
+            functions: [GPIOFunction::Input; NUM_PINS],
+            output_levels: 0,
+            input_levels: 0,
+            pwm_ctl: 0,
+            pwm_range: 0,
+            pwm_fifo: VecDeque::new(),
+            pwm_divider: 1,
+            pwm_busy_until_ns: 0,
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimulatedState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Set how many nanoseconds of virtual time a single GPIO write takes.
+    pub fn set_gpio_write_duration(&self, duration_ns: u64) {
+        self.state().gpio_write_ns = duration_ns;
+    }
+
+    /// Virtual time in nanoseconds.
+    #[must_use]
+    pub fn time_nanos(&self) -> u64 {
+        self.state().time_ns
+    }
+
+    /// The levels that were last written to the output pins.
+    #[must_use]
+    pub fn output_levels(&self) -> u32 {
+        self.state().output_levels
+    }
+
+    /// Set the levels that are read back from pins that are configured as inputs.
+    pub fn set_input_levels(&self, levels: u32) {
+        self.state().input_levels = levels;
+    }
+
+    /// The function that the pin is currently configured to.
+    #[must_use]
+    pub fn pin_function(&self, pin: u8) -> GPIOFunction {
+        self.state().functions[usize::from(pin)]
+    }
+
+    /// The period of the PWM clock in nanoseconds.
+    #[must_use]
+    pub fn pwm_clock_period_nanos(&self) -> u64 {
+        u64::from(self.state().pwm_divider) * PLLD_PERIOD_NS
+    }
+}
+
+impl RegisterBackend for SimulatedRegisterBackend {
+    fn select_function(&mut self, pin: u8, function: GPIOFunction) {
+        self.state().functions[usize::from(pin)] = function;
+    }
+
+    fn write_set_bits(&mut self, value: u32) {
+        let mut state = self.state();
+        state.output_levels |= value;
+        state.time_ns += state.gpio_write_ns;
+    }
+
+    fn write_clr_bits(&mut self, value: u32) {
+        let mut state = self.state();
+        state.output_levels &= !value;
+        state.time_ns += state.gpio_write_ns;
+    }
+
+    fn read_pin_level0(&self) -> u32 {
+        let state = self.state();
+        let output_mask = state.output_mask();
+        (state.output_levels & output_mask) | (state.input_levels & !output_mask)
+    }
+
+    fn get_time(&self) -> u64 {
+        self.state().time_ns / 1000
+    }
+
+    fn get_time_nanos(&self) -> u64 {
+        self.state().time_ns
+    }
+
+    fn sleep(&mut self, duration_us: u64) {
+        self.state().time_ns += duration_us * 1000;
+    }
+
+    fn sleep_at_most(&mut self, duration_us: u64) {
+        self.sleep(duration_us);
+    }
+
+    fn sleep_nanos(&mut self, duration_ns: u64) {
+        self.state().time_ns += duration_ns;
+    }
+
+    fn set_pwm_ctl(&mut self, value: u32) {
+        let mut state = self.state();
+        if value & PWM_CTL_CLRF1 != 0 {
+            state.pwm_fifo.clear();
+        }
+        let was_enabled = state.pwm_ctl & PWM_CTL_PWEN1 != 0;
+        if value & PWM_CTL_PWEN1 != 0 && !was_enabled {
+            // The channel starts shifting out the FIFO, one range worth of clock cycles per entry.
+            let entries = state.pwm_fifo.drain(..).count() as u64;
+            let period_ns = u64::from(state.pwm_divider) * PLLD_PERIOD_NS;
+            state.pwm_busy_until_ns =
+                state.time_ns + entries * u64::from(state.pwm_range) * period_ns;
+        }
+        state.pwm_ctl = value;
+    }
+
+    fn set_pwm_pulse_period(&mut self, value: u32) {
+        self.state().pwm_range = value;
+    }
+
+    fn push_pwm_fifo(&mut self, value: u32) {
+        self.state().pwm_fifo.push_back(value);
+    }
+
+    /// Polling the status register fast-forwards the virtual clock to the moment the FIFO runs empty.
+    fn pwm_fifo_empty(&self) -> bool {
+        let mut state = self.state();
+        state.time_ns = state.time_ns.max(state.pwm_busy_until_ns);
+        true
+    }
+
+    fn init_pwm_divider(&mut self, divider: u32) {
+        assert!(divider < (1 << 12)); // we only have 12 bits.
+        self.state().pwm_divider = divider.max(1);
+    }
+}
+
+/// The setup shared by the tests that run a matrix on the simulated backend.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::{
+        Canvas, PiChip, RGBMatrix, RGBMatrixConfig, SimulatedRegisterBackend, ThreadScheduling,
+    };
+
+    /// A single 32x16 panel. The chip is given, so it is not detected from the machine running the tests.
+    pub(crate) fn test_config() -> RGBMatrixConfig {
+        RGBMatrixConfig {
+            rows: 16,
+            cols: 32,
+            pi_chip: Some(PiChip::BCM2708),
+            // Unit tests must not change the settings of the host.
+            pin_update_thread: false,
+            disable_rt_throttling: false,
+            performance_governor: false,
+            update_thread_scheduling: ThreadScheduling::Unchanged,
+            ..Default::default()
+        }
+    }
+
+    /// Start a matrix on a new simulated backend.
+    pub(crate) fn simulated_matrix(config: RGBMatrixConfig) -> (RGBMatrix, Box<Canvas>) {
+        RGBMatrix::new_with_backend(config, 0, |_| Ok(SimulatedRegisterBackend::new())).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::test_config;
+    use crate::{gpio_bits, HardwareMapping, RGBMatrix, RGBMatrixConfig, SimulatedRegisterBackend};
+
+    #[test]
+    fn test_drive_matrix() {
+        let config = RGBMatrixConfig {
+            hardware_mapping: HardwareMapping::regular(),
+            rows: 32,
+            ..test_config()
+        };
+        let backend = SimulatedRegisterBackend::new();
+        let probe = backend.clone();
+        let (mut matrix, mut canvas) =
+            RGBMatrix::new_with_backend(config, 0, move |_| Ok(backend)).unwrap();
+        for _ in 0..3 {
+            canvas.fill(255, 0, 0);
+            canvas = matrix.update_on_vsync(canvas).unwrap();
+        }
+        assert!(probe.time_nanos() > 0);
+        assert_eq!(probe.pin_function(17), crate::GPIOFunction::Output);
+        // The output enable is driven by the hardware PWM.
+        assert_eq!(probe.pin_function(18), crate::GPIOFunction::Alt5);
+        drop(matrix);
+        assert_eq!(probe.output_levels() & gpio_bits!(17), 0);
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter},
+    fs::{File, OpenOptions},
+    io::{Read, Seek, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use thread_priority::{
+    set_current_thread_priority, set_thread_priority_and_policy, thread_native_id,
+    RealtimeThreadSchedulePolicy, ThreadPriority, ThreadSchedulePolicy,
+};
+
+use crate::{
+    chip::PiChip,
+    logging::{log_info, log_warn},
+    utils::{linux_has_isol_cpu, set_thread_affinity},
+    RGBMatrixConfig,
+};
+
+const RT_RUNTIME_PATH: &str = "/proc/sys/kernel/sched_rt_runtime_us";
+
+/// The scheduling of the update thread.
+///
+/// These options can be used with the `--update-thread-scheduling` flag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ThreadScheduling {
+    /// Keep the policy and priority of the process.
+    Unchanged,
+    /// The highest priority of the current policy.
+    #[default]
+    MaxPriority,
+    /// The realtime `SCHED_FIFO` policy with a priority between 1 and 99.
+    Fifo(u8),
+    /// The realtime `SCHED_RR` policy with a priority between 1 and 99.
+    RoundRobin(u8),
+}
+
+impl FromStr for ThreadScheduling {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_priority = |priority: &str| match priority.parse::<u8>() {
+            Ok(priority @ 1..=99) => Ok(priority),
+            _ => Err(format!(
+                "'{priority}' is not valid. The priority needs to be between 1 and 99"
+            )),
+        };
+        let ok = match s.split_once(':') {
+            Some(("Fifo", priority)) => Self::Fifo(parse_priority(priority)?),
+            Some(("RoundRobin", priority)) => Self::RoundRobin(parse_priority(priority)?),
+            None if s == "Unchanged" => Self::Unchanged,
+            None if s == "MaxPriority" => Self::MaxPriority,
+            _ => return Err(format!("'{s}' is not a valid thread scheduling.").into()),
+        };
+        Ok(ok)
+    }
+}
+
+impl Display for ThreadScheduling {
+    /// The scheduling as accepted by [`FromStr`].
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThreadScheduling::Unchanged => f.write_str("Unchanged"),
+            ThreadScheduling::MaxPriority => f.write_str("MaxPriority"),
+            ThreadScheduling::Fifo(priority) => write!(f, "Fifo:{priority}"),
+            ThreadScheduling::RoundRobin(priority) => write!(f, "RoundRobin:{priority}"),
+        }
+    }
+}
+
+impl ThreadScheduling {
+    /// Apply the scheduling to the current thread.
+    fn apply(self) {
+        let set_realtime = |policy, priority: u8| match priority.try_into() {
+            Ok(priority) => set_thread_priority_and_policy(
+                thread_native_id(),
+                ThreadPriority::Crossplatform(priority),
+                ThreadSchedulePolicy::Realtime(policy),
+            )
+            .is_ok(),
+            Err(_) => false,
+        };
+        let applied = match self {
+            ThreadScheduling::Unchanged => true,
+            ThreadScheduling::MaxPriority => {
+                set_current_thread_priority(ThreadPriority::Max).is_ok()
+            }
+            ThreadScheduling::Fifo(priority) => {
+                set_realtime(RealtimeThreadSchedulePolicy::Fifo, priority)
+            }
+            ThreadScheduling::RoundRobin(priority) => {
+                set_realtime(RealtimeThreadSchedulePolicy::RoundRobin, priority)
+            }
+        };
+        if !applied {
+            log_warn!("Could not set thread priority. This might lead to reduced performance.");
+        }
+    }
+}
+
+/// The system settings that were changed for the update thread. The previous values are restored when it
+/// is dropped.
+pub(crate) struct SystemTuning {
+    /// The files that were written, with their previous content. The files stay open, so the CPU governor
+    /// can still be restored after the privileges were dropped. The files in `/proc/sys` check the
+    /// permission on every write though, so the realtime throttling stays disabled after switching to
+    /// another user.
+    previous_values: Vec<(PathBuf, File, String)>,
+}
+
+impl SystemTuning {
+    /// Prepare the current thread and the system for the display updates, as far as the configuration
+    /// allows.
+    pub(crate) fn apply(chip: PiChip, config: &RGBMatrixConfig) -> Self {
+        let mut tuning = Self {
+            previous_values: Vec::new(),
+        };
+        let core_id = config
+            .update_thread_core
+            .unwrap_or_else(|| chip.num_cores() - 1);
+
+        // Pin the thread to one core to avoid the flicker resulting from context switching.
+        if config.pin_update_thread {
+            if !set_thread_affinity(core_id) {
+                log_warn!("Could not pin the update thread to core {core_id}.");
+            }
+
+            // If the user has not setup isolcpus, let them know about the performance improvement.
+            if chip.num_cores() > 1 && !linux_has_isol_cpu(core_id) {
+                log_info!(
+                    "Suggestion: to slightly improve display update, add\n\tisolcpus={core_id}\nat \
+                    the end of /boot/cmdline.txt and reboot"
+                );
+            }
+        }
+
+        // Disable realtime throttling.
+        if chip.num_cores() > 1
+            && config.disable_rt_throttling
+            && !tuning.replace(RT_RUNTIME_PATH.into(), "999000")
+        {
+            log_warn!("Could not disable realtime throttling");
+        }
+
+        // Set the core to performance mode.
+        if chip.num_cores() > 1
+            && config.performance_governor
+            && !tuning.replace(
+                format!("/sys/devices/system/cpu/cpu{core_id}/cpufreq/scaling_governor").into(),
+                "performance",
+            )
+        {
+            log_warn!("Could not set core {} to performance mode.", core_id + 1);
+        }
+
+        config.update_thread_scheduling.apply();
+
+        tuning
+    }
+
+    /// Write `value` to the file at `path` and remember the previous content. Returns whether the value was
+    /// written.
+    fn replace(&mut self, path: PathBuf, value: &str) -> bool {
+        let Ok(mut file) = OpenOptions::new().read(true).write(true).open(&path) else {
+            return false;
+        };
+        let mut previous_value = String::new();
+        if file.read_to_string(&mut previous_value).is_err() {
+            return false;
+        }
+        let previous_value = previous_value.trim().to_string();
+        if previous_value == value {
+            return true;
+        }
+        if file.rewind().is_err() || file.write_all(value.as_bytes()).is_err() {
+            return false;
+        }
+        self.previous_values.push((path, file, previous_value));
+        true
+    }
+}
+
+impl Drop for SystemTuning {
+    fn drop(&mut self) {
+        for (path, mut file, previous_value) in self.previous_values.drain(..).rev() {
+            if file.rewind().is_err() || file.write_all(previous_value.as_bytes()).is_err() {
+                log_warn!("Could not restore '{previous_value}' in {}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThreadScheduling;
+    use crate::{
+        simulated_backend::test_support::test_config, MatrixCreationError, RGBMatrixBuilder,
+        RGBMatrixConfig, SimulatedRegisterBackend,
+    };
+
+    #[test]
+    fn test_parse_thread_scheduling() {
+        for scheduling in [
+            ThreadScheduling::Unchanged,
+            ThreadScheduling::MaxPriority,
+            ThreadScheduling::Fifo(50),
+            ThreadScheduling::RoundRobin(99),
+        ] {
+            assert_eq!(
+                scheduling.to_string().parse::<ThreadScheduling>().ok(),
+                Some(scheduling)
+            );
+        }
+        assert!("Fifo:0".parse::<ThreadScheduling>().is_err());
+        assert!("Fifo:100".parse::<ThreadScheduling>().is_err());
+        assert!("Fifo".parse::<ThreadScheduling>().is_err());
+    }
+
+    #[test]
+    fn test_invalid_cpu_core() {
+        for core in [1, 1024] {
+            let config = RGBMatrixConfig {
+                update_thread_core: Some(core),
+                ..test_config()
+            };
+            let result = RGBMatrixBuilder::new(config)
+                .build_with_backend(|_| Ok(SimulatedRegisterBackend::new()));
+            assert!(matches!(result, Err(MatrixCreationError::InvalidCpuCore(c)) if c == core));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    fmt::Write as _,
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    canvas::Canvas,
+    register_backend::RegisterBackend,
+    registers::{GPIOFunction, MonotonicTimer, Timer},
+    rgb_matrix::MatrixCreationError,
+    PiChip,
+};
+
+/// Upper half block. The foreground color is the upper pixel, the background color the lower one.
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+const RESET: &str = "\x1b[0m";
+const CLEAR_SCREEN: &str = "\x1b[2J";
+const CURSOR_HOME: &str = "\x1b[H";
+/// The shortest time between two redraws of the preview. Terminals can't keep up with the refresh rate.
+const MIN_REDRAW_INTERVAL: Duration = Duration::from_millis(40);
+
+/// Render the visible pixels of a canvas, i.e. after all pixel mappers were applied, as ANSI truecolor
+/// half block characters. Every line of text shows two rows of pixels.
+#[must_use]
+pub fn render_ansi(canvas: &Canvas) -> String {
+    let mut output = String::new();
+    for y in (0..canvas.height()).step_by(2) {
+        for x in 0..canvas.width() {
+            let [r, g, b] = canvas.get_pixel(x, y).unwrap_or_default();
+            let _ = write!(output, "\x1b[38;2;{r};{g};{b}m");
+            match canvas.get_pixel(x, y + 1) {
+                Some([r, g, b]) => {
+                    let _ = write!(output, "\x1b[48;2;{r};{g};{b}m");
+                }
+                // The lower half of the last line of an odd height is left empty.
+                None => output.push_str("\x1b[49m"),
+            }
+            output.push(UPPER_HALF_BLOCK);
+        }
+        output.push_str(RESET);
+        output.push('\n');
+    }
+    output
+}
+
+/// Shows the frames of the update thread in the terminal when the matrix runs in preview mode.
+pub(crate) struct TerminalPreview {
+    cleared: bool,
+    /// Whether a new canvas was received since the last redraw.
+    outdated: bool,
+    last_redraw: Option<Instant>,
+}
+
+impl TerminalPreview {
+    pub(crate) fn new() -> Self {
+        Self {
+            cleared: false,
+            outdated: true,
+            last_redraw: None,
+        }
+    }
+
+    /// Mark the shown canvas as replaced, so the next [`TerminalPreview::update`] redraws it.
+    pub(crate) fn canvas_changed(&mut self) {
+        self.outdated = true;
+    }
+
+    /// Redraw the terminal with the canvas if it changed, but at most every [`MIN_REDRAW_INTERVAL`].
+    pub(crate) fn update(&mut self, canvas: &Canvas) {
+        let too_early = self
+            .last_redraw
+            .is_some_and(|last_redraw| last_redraw.elapsed() < MIN_REDRAW_INTERVAL);
+        if self.outdated && !too_early {
+            self.show(canvas);
+        }
+    }
+
+    /// Redraw the terminal with the canvas. Errors are ignored, there is nobody to report them to.
+    pub(crate) fn show(&mut self, canvas: &Canvas) {
+        self.outdated = false;
+        self.last_redraw = Some(Instant::now());
+        let mut frame = String::new();
+        if !self.cleared {
+            frame.push_str(CLEAR_SCREEN);
+            self.cleared = true;
+        }
+        frame.push_str(CURSOR_HOME);
+        frame.push_str(&render_ansi(canvas));
+        let mut stdout = stdout().lock();
+        let _ = stdout.write_all(frame.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+/// The register backend of the preview mode. GPIO writes are discarded and time is real, so the update
+/// thread keeps its frame rate.
+pub(crate) struct PreviewRegisterBackend {
+    timer: MonotonicTimer,
+}
+
+impl PreviewRegisterBackend {
+    pub(crate) fn new(_chip: PiChip) -> Result<Self, MatrixCreationError> {
+        Ok(Self {
+            timer: MonotonicTimer,
+        })
+    }
+}
+
+impl RegisterBackend for PreviewRegisterBackend {
+    fn select_function(&mut self, _pin: u8, _function: GPIOFunction) {}
+
+    fn write_set_bits(&mut self, _value: u32) {}
+
+    fn write_clr_bits(&mut self, _value: u32) {}
+
+    fn read_pin_level0(&self) -> u32 {
+        0
+    }
+
+    fn get_time(&self) -> u64 {
+        self.timer.get_time()
+    }
+
+    fn sleep(&mut self, duration_us: u64) {
+        self.timer.sleep(duration_us);
+    }
+
+    fn sleep_at_most(&mut self, duration_us: u64) {
+        self.timer.sleep_at_most(duration_us);
+    }
+
+    fn sleep_nanos(&mut self, duration_ns: u64) {
+        self.timer.sleep_nanos(duration_ns);
+    }
+
+    fn supports_hardware_pwm(&self) -> bool {
+        false
+    }
+
+    fn set_pwm_ctl(&mut self, _value: u32) {}
+
+    fn set_pwm_pulse_period(&mut self, _value: u32) {}
+
+    fn push_pwm_fifo(&mut self, _value: u32) {}
+
+    fn pwm_fifo_empty(&self) -> bool {
+        true
+    }
+
+    fn init_pwm_divider(&mut self, _divider: u32) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_ansi;
+    use crate::{
+        simulated_backend::test_support::{simulated_matrix, test_config},
+        RGBMatrixConfig,
+    };
+
+    #[test]
+    fn test_render_ansi() {
+        let (_matrix, mut canvas) = simulated_matrix(RGBMatrixConfig {
+            pixelmapper: vec!["Rotate:90".parse().unwrap()],
+            ..test_config()
+        });
+        canvas.set_pixel(0, 0, 255, 0, 0);
+        canvas.set_pixel(0, 1, 0, 0, 255);
+        let rendered = render_ansi(&canvas);
+        let lines: Vec<&str> = rendered.lines().collect();
+        // The rotated canvas is 16 pixels wide and 32 pixels high.
+        assert_eq!(lines.len(), 16);
+        assert_eq!(lines[0].matches('\u{2580}').count(), 16);
+        assert!(lines[0].starts_with("\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{2580}"));
+        assert!(lines[1].starts_with("\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m\u{2580}"));
+    }
+}
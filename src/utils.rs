@@ -4,7 +4,7 @@ use std::{
     time::Instant,
 };
 
-use libc::{cpu_set_t, sched_setaffinity, CPU_SET};
+use libc::{cpu_set_t, sched_setaffinity, CPU_SET, CPU_SETSIZE};
 
 /// Sets the bits that are passed as arguments.
 #[macro_export]
@@ -37,13 +37,17 @@ pub(crate) fn linux_has_isol_cpu(cpu: usize) -> bool {
         .any(|line| line.unwrap().contains(&cpu.to_string()))
 }
 
+/// Pin the current thread to the core `core_id`. Returns whether the affinity was set.
 pub fn set_thread_affinity(core_id: usize) -> bool {
+    if core_id >= CPU_SETSIZE as usize {
+        return false;
+    }
     let mut set: cpu_set_t = unsafe { std::mem::zeroed() };
     unsafe { CPU_SET(core_id, &mut set) }
     let cpusetsize = std::mem::size_of::<cpu_set_t>();
     let mask = &set;
     let res = unsafe { sched_setaffinity(0, cpusetsize, mask) };
-    res != 0
+    res == 0
 }
 
 const WINDOW_LENGTH: usize = 60;
@@ -0,0 +1,617 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    hardware_mapping::HardwareMapping,
+    register_backend::RegisterBackend,
+    registers::{GPIOFunction, PWM_CTL_CLRF1, PWM_CTL_PWEN1},
+};
+
+/// The PWM clock is derived from the 500 MHz PLLD.
+const PLLD_PERIOD_NS: u64 = 2;
+
+/// A single register access of the update thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegisterEvent {
+    /// A pin was switched to a different function.
+    SelectFunction(u8, GPIOFunction),
+    /// A write to the `GPSET0` register.
+    SetBits(u32),
+    /// A write to the `GPCLR0` register.
+    ClearBits(u32),
+    /// A write to the PWM control register.
+    PwmControl(u32),
+    /// A write to the range register of PWM channel 1.
+    PwmRange(u32),
+    /// A value pushed into the PWM FIFO.
+    PwmFifoPush(u32),
+    /// The PWM clock divider was set.
+    PwmDivider(u32),
+}
+
+/// A register access together with the time it happened at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecordedEvent {
+    pub time_ns: u64,
+    pub event: RegisterEvent,
+}
+
+/// A register backend that records every write before passing it on to another backend. Combined with
+/// the [`SimulatedRegisterBackend`](crate::SimulatedRegisterBackend) the timestamps are exact, since they
+/// come from its virtual clock.
+///
+/// The recorded events are shared between clones of the [`WaveformRecording`] returned by
+/// [`RecordingRegisterBackend::recording`], so they can be inspected while the backend is owned by the
+/// update thread.
+pub struct RecordingRegisterBackend<B: RegisterBackend> {
+    inner: B,
+    recording: WaveformRecording,
+}
+
+impl<B: RegisterBackend> RecordingRegisterBackend<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            recording: WaveformRecording::default(),
+        }
+    }
+
+    /// A handle to the events recorded by this backend.
+    #[must_use]
+    pub fn recording(&self) -> WaveformRecording {
+        self.recording.clone()
+    }
+
+    fn record(&self, event: RegisterEvent) {
+        let time_ns = self.inner.get_time_nanos();
+        self.recording
+            .events()
+            .push(RecordedEvent { time_ns, event });
+    }
+}
+
+impl<B: RegisterBackend> RegisterBackend for RecordingRegisterBackend<B> {
+    fn select_function(&mut self, pin: u8, function: GPIOFunction) {
+        self.record(RegisterEvent::SelectFunction(pin, function));
+        self.inner.select_function(pin, function);
+    }
+
+    fn write_set_bits(&mut self, value: u32) {
+        self.record(RegisterEvent::SetBits(value));
+        self.inner.write_set_bits(value);
+    }
+
+    fn write_clr_bits(&mut self, value: u32) {
+        self.record(RegisterEvent::ClearBits(value));
+        self.inner.write_clr_bits(value);
+    }
+
+    fn read_pin_level0(&self) -> u32 {
+        self.inner.read_pin_level0()
+    }
+
+    fn get_time(&self) -> u64 {
+        self.inner.get_time()
+    }
+
+    fn get_time_nanos(&self) -> u64 {
+        self.inner.get_time_nanos()
+    }
+
+    fn sleep(&mut self, duration_us: u64) {
+        self.inner.sleep(duration_us);
+    }
+
+    fn sleep_at_most(&mut self, duration_us: u64) {
+        self.inner.sleep_at_most(duration_us);
+    }
+
+    fn sleep_nanos(&mut self, duration_ns: u64) {
+        self.inner.sleep_nanos(duration_ns);
+    }
+
+    fn supports_hardware_pwm(&self) -> bool {
+        self.inner.supports_hardware_pwm()
+    }
+
+    fn set_pwm_ctl(&mut self, value: u32) {
+        self.record(RegisterEvent::PwmControl(value));
+        self.inner.set_pwm_ctl(value);
+    }
+
+    fn set_pwm_pulse_period(&mut self, value: u32) {
+        self.record(RegisterEvent::PwmRange(value));
+        self.inner.set_pwm_pulse_period(value);
+    }
+
+    fn push_pwm_fifo(&mut self, value: u32) {
+        self.record(RegisterEvent::PwmFifoPush(value));
+        self.inner.push_pwm_fifo(value);
+    }
+
+    fn pwm_fifo_empty(&self) -> bool {
+        self.inner.pwm_fifo_empty()
+    }
+
+    fn init_pwm_divider(&mut self, divider: u32) {
+        self.record(RegisterEvent::PwmDivider(divider));
+        self.inner.init_pwm_divider(divider);
+    }
+}
+
+/// Shared handle to the events recorded by a [`RecordingRegisterBackend`].
+#[derive(Clone, Default)]
+pub struct WaveformRecording {
+    events: Arc<Mutex<Vec<RecordedEvent>>>,
+}
+
+impl WaveformRecording {
+    fn events(&self) -> MutexGuard<'_, Vec<RecordedEvent>> {
+        self.events.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A copy of all events recorded so far.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<RecordedEvent> {
+        self.events().clone()
+    }
+
+    /// Remove and return all events recorded so far.
+    pub fn take(&self) -> Vec<RecordedEvent> {
+        std::mem::take(&mut *self.events())
+    }
+
+    /// Decode the events recorded so far into HUB75 signals.
+    #[must_use]
+    pub fn decode(&self, hardware_mapping: &HardwareMapping) -> Hub75Trace {
+        Hub75Trace::decode(&self.events(), hardware_mapping)
+    }
+}
+
+/// The control signals of a HUB75 connector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hub75Signal {
+    Clock,
+    Strobe,
+    OutputEnable,
+    A,
+    B,
+    C,
+    D,
+    E,
+}
+
+impl Hub75Signal {
+    const ALL: [Hub75Signal; 8] = [
+        Hub75Signal::Clock,
+        Hub75Signal::Strobe,
+        Hub75Signal::OutputEnable,
+        Hub75Signal::A,
+        Hub75Signal::B,
+        Hub75Signal::C,
+        Hub75Signal::D,
+        Hub75Signal::E,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Hub75Signal::Clock => "clk",
+            Hub75Signal::Strobe => "lat",
+            Hub75Signal::OutputEnable => "oe",
+            Hub75Signal::A => "a",
+            Hub75Signal::B => "b",
+            Hub75Signal::C => "c",
+            Hub75Signal::D => "d",
+            Hub75Signal::E => "e",
+        }
+    }
+
+    fn bits(self, hardware_mapping: &HardwareMapping) -> u32 {
+        let h = hardware_mapping;
+        match self {
+            Hub75Signal::Clock => h.clock,
+            Hub75Signal::Strobe => h.strobe,
+            Hub75Signal::OutputEnable => h.output_enable,
+            Hub75Signal::A => h.a,
+            Hub75Signal::B => h.b,
+            Hub75Signal::C => h.c,
+            Hub75Signal::D => h.d,
+            Hub75Signal::E => h.e,
+        }
+    }
+}
+
+/// A level change of a HUB75 signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalTransition {
+    pub time_ns: u64,
+    pub signal: Hub75Signal,
+    pub level: bool,
+}
+
+/// Decoded HUB75 activity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hub75Event {
+    /// A rising edge of the clock with the levels of all GPIO pins at that moment.
+    Clock { time_ns: u64, levels: u32 },
+    /// A change of the address lines. `address` holds their levels, A in the lowest bit.
+    Address { time_ns: u64, address: u8 },
+    /// A rising edge of the strobe. `address` holds the levels of the address lines, A in the lowest bit.
+    Latch {
+        time_ns: u64,
+        address: u8,
+        output_enabled: bool,
+    },
+    /// An interval in which the output enable was active. Pulses of the hardware PWM are reported when they
+    /// start, pulses driven through GPIO writes when they end.
+    OutputEnable { start_ns: u64, duration_ns: u64 },
+}
+
+/// A latch together with the number of clock cycles since the previous latch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Latch {
+    pub time_ns: u64,
+    pub address: u8,
+    pub output_enabled: bool,
+    pub clocks: usize,
+}
+
+/// The HUB75 signals that were decoded from the register events. The output enable is decoded from the
+/// GPIO levels or, while the pin is switched to its alternate function, from the PWM FIFO.
+#[derive(Debug, Clone, Default)]
+pub struct Hub75Trace {
+    transitions: Vec<SignalTransition>,
+    events: Vec<Hub75Event>,
+}
+
+impl Hub75Trace {
+    /// Decode the signals from a list of register events.
+    #[must_use]
+    pub fn decode(recorded: &[RecordedEvent], hardware_mapping: &HardwareMapping) -> Self {
+        let h = hardware_mapping;
+        let mut transitions = Vec::new();
+        let mut events = Vec::new();
+
+        let mut levels: u32 = 0;
+        let mut oe_on_pwm = false;
+        let mut pwm_enabled = false;
+        let mut pwm_range: u64 = 0;
+        let mut pwm_divider: u64 = 1;
+        let mut pwm_fifo_sum: u64 = 0;
+        // The output enable is active low.
+        let mut oe_active_until: Option<u64> = None;
+        let mut oe_falling_edge: Option<u64> = None;
+
+        let level_of = |levels: u32, signal: Hub75Signal| levels & signal.bits(h) != 0;
+        let address_of = |levels: u32| {
+            [
+                Hub75Signal::A,
+                Hub75Signal::B,
+                Hub75Signal::C,
+                Hub75Signal::D,
+                Hub75Signal::E,
+            ]
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| level_of(levels, **line))
+            .fold(0, |address, (bit, _)| address | (1 << bit))
+        };
+
+        Hub75Signal::ALL.iter().for_each(|&signal| {
+            let level = signal == Hub75Signal::OutputEnable;
+            transitions.push(SignalTransition {
+                time_ns: 0,
+                signal,
+                level,
+            });
+        });
+
+        for RecordedEvent { time_ns, event } in recorded.iter().copied() {
+            // Close a PWM pulse that ended before this event.
+            if let Some(end) = oe_active_until {
+                if end <= time_ns {
+                    transitions.push(SignalTransition {
+                        time_ns: end,
+                        signal: Hub75Signal::OutputEnable,
+                        level: true,
+                    });
+                    oe_active_until = None;
+                }
+            }
+
+            let new_levels = match event {
+                RegisterEvent::SetBits(bits) => levels | bits,
+                RegisterEvent::ClearBits(bits) => levels & !bits,
+                RegisterEvent::SelectFunction(pin, function) => {
+                    // Pins above 31 are not in the first bank and can't be the output enable.
+                    let pin_bit = 1u32.checked_shl(u32::from(pin)).unwrap_or(0);
+                    if h.output_enable & pin_bit != 0 {
+                        oe_on_pwm = !matches!(function, GPIOFunction::Input | GPIOFunction::Output);
+                    }
+                    continue;
+                }
+                RegisterEvent::PwmControl(value) => {
+                    if value & PWM_CTL_CLRF1 != 0 {
+                        pwm_fifo_sum = 0;
+                    }
+                    let enable = value & PWM_CTL_PWEN1 != 0;
+                    if enable && !pwm_enabled && oe_on_pwm {
+                        let duration_ns = pwm_fifo_sum * pwm_divider * PLLD_PERIOD_NS;
+                        pwm_fifo_sum = 0;
+                        if duration_ns > 0 {
+                            transitions.push(SignalTransition {
+                                time_ns,
+                                signal: Hub75Signal::OutputEnable,
+                                level: false,
+                            });
+                            events.push(Hub75Event::OutputEnable {
+                                start_ns: time_ns,
+                                duration_ns,
+                            });
+                            oe_active_until = Some(time_ns + duration_ns);
+                        }
+                    }
+                    pwm_enabled = enable;
+                    continue;
+                }
+                RegisterEvent::PwmRange(range) => {
+                    pwm_range = u64::from(range);
+                    continue;
+                }
+                RegisterEvent::PwmFifoPush(value) => {
+                    pwm_fifo_sum += u64::from(value).min(pwm_range);
+                    continue;
+                }
+                RegisterEvent::PwmDivider(divider) => {
+                    pwm_divider = u64::from(divider.max(1));
+                    continue;
+                }
+            };
+
+            // The address lines are set before the strobe, also if both change with the same write.
+            let address = address_of(new_levels);
+            if address != address_of(levels) {
+                events.push(Hub75Event::Address { time_ns, address });
+            }
+
+            for signal in Hub75Signal::ALL {
+                if signal == Hub75Signal::OutputEnable && oe_on_pwm {
+                    continue;
+                }
+                let before = level_of(levels, signal);
+                let after = level_of(new_levels, signal);
+                if before == after {
+                    continue;
+                }
+                transitions.push(SignalTransition {
+                    time_ns,
+                    signal,
+                    level: after,
+                });
+                match signal {
+                    Hub75Signal::Clock if after => events.push(Hub75Event::Clock {
+                        time_ns,
+                        levels: new_levels,
+                    }),
+                    Hub75Signal::Strobe if after => {
+                        let output_enabled = if oe_on_pwm {
+                            oe_active_until.is_some()
+                        } else {
+                            !level_of(new_levels, Hub75Signal::OutputEnable)
+                        };
+                        events.push(Hub75Event::Latch {
+                            time_ns,
+                            address,
+                            output_enabled,
+                        });
+                    }
+                    Hub75Signal::OutputEnable if !after => oe_falling_edge = Some(time_ns),
+                    Hub75Signal::OutputEnable => {
+                        if let Some(start_ns) = oe_falling_edge.take() {
+                            events.push(Hub75Event::OutputEnable {
+                                start_ns,
+                                duration_ns: time_ns - start_ns,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            levels = new_levels;
+        }
+
+        if let Some(end) = oe_active_until {
+            transitions.push(SignalTransition {
+                time_ns: end,
+                signal: Hub75Signal::OutputEnable,
+                level: true,
+            });
+        }
+        transitions.sort_by_key(|transition| transition.time_ns);
+
+        Self {
+            transitions,
+            events,
+        }
+    }
+
+    /// All level changes, ordered by time.
+    #[must_use]
+    pub fn transitions(&self) -> &[SignalTransition] {
+        &self.transitions
+    }
+
+    /// Clock edges, address changes, latches and output enable pulses in the order they happened.
+    #[must_use]
+    pub fn events(&self) -> &[Hub75Event] {
+        &self.events
+    }
+
+    /// All latches with the number of pixels that were clocked in before each of them.
+    #[must_use]
+    pub fn latches(&self) -> Vec<Latch> {
+        let mut clocks = 0;
+        let mut latches = Vec::new();
+        self.events.iter().for_each(|event| match *event {
+            Hub75Event::Clock { .. } => clocks += 1,
+            Hub75Event::Latch {
+                time_ns,
+                address,
+                output_enabled,
+            } => {
+                latches.push(Latch {
+                    time_ns,
+                    address,
+                    output_enabled,
+                    clocks,
+                });
+                clocks = 0;
+            }
+            Hub75Event::Address { .. } | Hub75Event::OutputEnable { .. } => {}
+        });
+        latches
+    }
+
+    /// All intervals in which the output enable was active, as `(start_ns, duration_ns)`.
+    #[must_use]
+    pub fn output_enable_pulses(&self) -> Vec<(u64, u64)> {
+        self.events
+            .iter()
+            .filter_map(|event| match *event {
+                Hub75Event::OutputEnable {
+                    start_ns,
+                    duration_ns,
+                } => Some((start_ns, duration_ns)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Export the signals in the Value Change Dump format, e.g. for GTKWave.
+    pub fn write_vcd<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let identifier = |signal: Hub75Signal| {
+            let index = Hub75Signal::ALL.iter().position(|s| *s == signal).unwrap();
+            char::from(b'!' + index as u8)
+        };
+        writeln!(writer, "$timescale 1ns $end")?;
+        writeln!(writer, "$scope module hub75 $end")?;
+        for signal in Hub75Signal::ALL {
+            writeln!(
+                writer,
+                "$var wire 1 {} {} $end",
+                identifier(signal),
+                signal.name()
+            )?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+
+        let mut current_time = None;
+        for transition in &self.transitions {
+            if current_time != Some(transition.time_ns) {
+                writeln!(writer, "#{}", transition.time_ns)?;
+                current_time = Some(transition.time_ns);
+            }
+            writeln!(
+                writer,
+                "{}{}",
+                u8::from(transition.level),
+                identifier(transition.signal)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hub75Trace, RecordedEvent, RecordingRegisterBackend, RegisterEvent};
+    use crate::{
+        canvas::{Canvas, PixelDesignator, PixelDesignatorMap},
+        gpio::Gpio,
+        GPIOFunction, HardwareMapping, PiChip, RGBMatrixConfig, SimulatedRegisterBackend,
+    };
+
+    fn dump_one_frame(config: &RGBMatrixConfig) -> Hub75Trace {
+        let backend = RecordingRegisterBackend::new(SimulatedRegisterBackend::new());
+        let recording = backend.recording();
+        let mut address_setter = config.row_setter.create(config);
+        let mut gpio =
+            Gpio::new(PiChip::BCM2708, config, address_setter.as_ref(), backend).unwrap();
+        let designator = PixelDesignator::new(&config.hardware_mapping, config.led_sequence);
+        let width = config.cols * config.chain_length;
+        let height = config.rows * config.parallel;
+        let map = PixelDesignatorMap::new(designator, width, height, config);
+        let mut canvas = Canvas::new(config, map);
+        canvas.fill(255, 255, 255);
+        canvas.dump_to_matrix(
+            &mut gpio,
+            &config.hardware_mapping,
+            address_setter.as_mut(),
+            0,
+            config
+                .hardware_mapping
+                .get_color_clock_mask(config.parallel),
+        );
+        gpio.wait_pulse_finished();
+        recording.decode(&config.hardware_mapping)
+    }
+
+    #[test]
+    fn test_clocks_and_latches() {
+        for hardware_mapping in [HardwareMapping::regular(), HardwareMapping::adafruit_hat()] {
+            let config = RGBMatrixConfig {
+                hardware_mapping,
+                rows: 32,
+                cols: 32,
+                chain_length: 2,
+                interlaced: true,
+                ..Default::default()
+            };
+            let trace = dump_one_frame(&config);
+            let latches = trace.latches();
+            assert_eq!(latches.len(), 16 * config.pwm_bits);
+            assert!(latches.iter().all(|latch| latch.clocks == 64));
+            assert!(latches.iter().all(|latch| !latch.output_enabled));
+            let expected_rows = (0..8).map(|r| 2 * r).chain((0..8).map(|r| 2 * r + 1));
+            let expected_addresses = expected_rows
+                .flat_map(|row| std::iter::repeat_n(row, config.pwm_bits))
+                .collect::<Vec<_>>();
+            let addresses = latches
+                .iter()
+                .map(|latch| latch.address)
+                .collect::<Vec<_>>();
+            assert_eq!(addresses, expected_addresses);
+            assert_eq!(trace.output_enable_pulses().len(), latches.len());
+        }
+    }
+
+    #[test]
+    fn test_high_pin_function() {
+        let recorded = [RecordedEvent {
+            time_ns: 0,
+            event: RegisterEvent::SelectFunction(45, GPIOFunction::Alt0),
+        }];
+        let trace = Hub75Trace::decode(&recorded, &HardwareMapping::regular());
+        assert!(trace.output_enable_pulses().is_empty());
+    }
+
+    #[test]
+    fn test_vcd_export() {
+        let config = RGBMatrixConfig {
+            rows: 16,
+            cols: 16,
+            pwm_bits: 1,
+            ..Default::default()
+        };
+        let trace = dump_one_frame(&config);
+        let mut vcd = Vec::new();
+        trace.write_vcd(&mut vcd).unwrap();
+        let vcd = String::from_utf8(vcd).unwrap();
+        assert!(vcd.starts_with("$timescale 1ns $end"));
+        assert!(vcd.contains("$var wire 1 ! clk $end"));
+        assert!(vcd.contains("$enddefinitions $end"));
+    }
+}